@@ -3,11 +3,16 @@ pub struct DefinitionNode<'a> {
     pub options: Option<Vec<OptionNode<'a>>>,
     pub code: Option<CodeNode<'a>>,
     pub definitions: Option<Vec<DefinitionPairNode<'a>>>,
+    pub start_conditions: Option<Vec<StartConditionNode<'a>>>,
+    // `array` or `pointer`, the type of yytext declared by `%array` or `%pointer`
+    pub yytext_type: Option<&'a str>,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct OptionNode<'a> {
-    pub value: &'a str,
+    pub name: &'a str,
+    // the value of `name=value` without the quotes
+    pub value: Option<&'a str>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -21,9 +26,16 @@ pub struct DefinitionPairNode<'a> {
     pub definition: &'a str,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct StartConditionNode<'a> {
+    pub name: &'a str,
+    // declared with %x, rules without start conditions are not active in it
+    pub exclusive: bool,
+}
+
 impl<'a> From<&'a str> for OptionNode<'a> {
-    fn from(value: &'a str) -> Self {
-        OptionNode { value }
+    fn from(name: &'a str) -> Self {
+        OptionNode { name, value: None }
     }
 }
 
@@ -8,4 +8,7 @@ pub struct RuleNode<'a> {
 pub struct RulePairNode<'a> {
     pub pattern: &'a str,
     pub action: &'a str,
+    // empty if the rule is active in INITIAL and the inclusive start conditions,
+    // `*` for all start conditions
+    pub start_conditions: Vec<&'a str>,
 }
@@ -1,1169 +1,1035 @@
+use super::variable::Variable;
+
+// the functions a scanner exports, renamed by `%option prefix`
+pub const PREFIX_FUNCTIONS: &[&str] = &[
+    "yylex",
+    "yyrestart",
+    "yy_create_buffer",
+    "yy_delete_buffer",
+    "yy_switch_to_buffer",
+    "yypush_buffer_state",
+    "yypop_buffer_state",
+    "yy_scan_buffer",
+    "yy_scan_bytes",
+    "yy_scan_string",
+    "yylex_init",
+    "yylex_init_extra",
+    "yylex_destroy",
+    "yyget_extra",
+    "yyset_extra",
+    "yyget_in",
+    "yyset_in",
+    "yyget_out",
+    "yyset_out",
+    "yyget_text",
+    "yyget_leng",
+    "yyget_lineno",
+    "yyset_lineno",
+];
+
+// the global variables a scanner exports, renamed by `%option prefix`
+pub const PREFIX_VARIABLES: &[&str] = &[
+    "yyin",
+    "yyout",
+    "yytext",
+    "yyleng",
+    "yylineno",
+    "yylocation",
+];
+
 pub const PREPARE: &str = r#"#include <stdio.h>
 #include <stdlib.h>
-
+#include <string.h>
+#ifndef YY_NO_UNISTD_H
+#include <unistd.h>
+#endif
+
+/* the same as in the header, which the definitions may include */
+#ifndef YY_TYPEDEF_YY_BUFFER_STATE
+#define YY_TYPEDEF_YY_BUFFER_STATE
 typedef unsigned long IdxType;
 
-#define YYTEXT_MAXLEN 1024
-
-FILE *yyin = NULL, *yyout = NULL;
-
-char *g_buffer;
-char *g_buffer_ptr;
-IdxType g_buflen;
-IdxType g_bufidx;
+/* an input source being scanned, a file or bytes in memory */
+typedef struct yy_buffer_state *YY_BUFFER_STATE;
+#endif
 
-char yytext[YYTEXT_MAXLEN];
-IdxType yyleng;
-
-void yy_read_buffer() {
-  fseek(yyin, 0, SEEK_END);
-  g_buflen = ftell(yyin);
-  rewind(yyin);
+/* for the static helpers a scanner may not call */
+#ifdef __GNUC__
+#define YY_UNUSED __attribute__((unused))
+#else
+#define YY_UNUSED
+#endif
 
-  g_buffer = malloc(g_buflen + 1);
-  if (!g_buffer) {
-    perror("Failed to allocate memory for buffer");
-  }
+/* report an error the scanner cannot recover from and exit */
+#ifndef YY_FATAL_ERROR
+#define YY_FATAL_ERROR(msg) yy_fatal_error(msg)
+#endif
 
-  fread(g_buffer, 1, g_buflen, yyin);
-  g_buffer[g_buflen] = '\0';
-  fclose(yyin);
+static YY_UNUSED void yy_fatal_error(const char *msg) {
+  fprintf(stderr, "%s\n", msg);
+  exit(EXIT_FAILURE);
 }
 
-"#;
+#ifndef YY_BUF_SIZE
+#define YY_BUF_SIZE 16384
+#endif
 
-pub const REGEX: &str = r#"
-/*
- * Merged regex library
- * For embedding into lers projects
- */
-
-#include <stdbool.h>
-#include <stddef.h>
-#include <stdio.h>
-#include <stdlib.h>
+#ifndef YYLMAX
+#define YYLMAX 8192
+#endif
 
 /*
- * ============================================================================
- * state.c - State management
- * ============================================================================
+ * read at most max_size bytes into buf, and assign the number read to result,
+ * a line at a time from a terminal so that the tokens come as they are typed
  */
+#ifndef YY_INPUT
+#define YY_INPUT(buf, result, max_size)                                        \
+  if (YY_CURRENT_BUFFER->interactive) {                                        \
+    int c = '*';                                                               \
+    IdxType n;                                                                 \
+    for (n = 0; n < (max_size) && (c = getc(yyin)) != EOF && c != '\n'; ++n)   \
+      (buf)[n] = (char)c;                                                      \
+    if (c == '\n')                                                             \
+      (buf)[n++] = (char)c;                                                    \
+    if (c == EOF && ferror(yyin))                                              \
+      YY_FATAL_ERROR("input in scanner failed");                               \
+    (result) = n;                                                              \
+  } else if (((result) = fread(buf, 1, max_size, yyin)) == 0 &&                \
+             ferror(yyin))                                                     \
+    YY_FATAL_ERROR("input in scanner failed");
+#endif
+
+struct yy_buffer_state {
+  FILE *input;
+  char *base;
+  /* the start of the next token */
+  char *pos;
+  /* the bytes allocated, and read so far */
+  IdxType size;
+  IdxType len;
+  /* whether base is freed with the buffer */
+  int owned;
+  /* whether the input is a terminal, read a line at a time */
+  int interactive;
+  int eof;
+  int at_bol;
+};
 
-#define MAX 999
+"#;
 
-typedef unsigned short State;
+pub const GLOBAL_SCANNER: &str = r#"/* the state is global, the functions take no scanner */
+#define YY_ONLY_ARG void
+#define YY_LAST_ARG
+#define YY_CALL_ONLY_ARG
+#define YY_CALL_LAST_ARG
+#define YY_DECL_GUTS_VAR()
 
-typedef struct States {
-  State states[MAX];
-  size_t len;
-} States;
+"#;
 
-/* create an empty container of states */
-States *new_states() {
-  States *s = (States *)malloc(sizeof(States));
-  s->len = 0;
-  return s;
-}
+pub const REENTRANT_SCANNER: &str = r#"#include <errno.h>
 
-/* push a state into the container */
-void push_state(States *s, State state) {
-  s->states[s->len] = state;
-  ++(s->len);
-}
+/* the state of a scanner, passed to yylex and the functions in the actions */
+#ifndef YY_TYPEDEF_YY_SCANNER_T
+#define YY_TYPEDEF_YY_SCANNER_T
+typedef void *yyscan_t;
+#endif
 
-/* if the states is empty */
-bool states_is_empty(States *s) { return s->len == 0; }
+#ifndef YY_EXTRA_TYPE
+#define YY_EXTRA_TYPE void *
+#endif
 
-/* if the container has the state */
-bool have_state(States *s, State state) {
-  for (size_t i = 0; i < s->len; ++i)
-    if (s->states[i] == state)
-      return true;
-  return false;
-}
+#define YY_ONLY_ARG yyscan_t yyscanner
+#define YY_LAST_ARG , yyscan_t yyscanner
+#define YY_CALL_ONLY_ARG yyscanner
+#define YY_CALL_LAST_ARG , yyscanner
+#define YY_DECL_GUTS_VAR()                                                     \
+  struct yyguts_t *yyg = (struct yyguts_t *)yyscanner;                         \
+  (void)yyg
 
-/* if two containers have the same state, return the state */
-State get_shared_states(States *s1, States *s2) {
-  for (size_t i = 0; i < s1->len; ++i) {
-    for (size_t j = 0; j < s2->len; ++j) {
-      if (s1->states[i] == s2->states[j])
-        return s1->states[i];
-    }
-  }
-  return 0;
-}
+"#;
 
-/* print states of the container */
-void print_states(States *s) {
-  printf("States[");
-  if (s->len == 0) {
-    printf("]\n");
-  } else if (s->len == 1) {
-    printf("%hu]\n", s->states[0]);
-  } else {
-    for (size_t i = 0; i < s->len - 1; ++i) {
-      printf("%hu, ", s->states[i]);
-    }
-    printf("%hu]\n", s->states[s->len - 1]);
+pub const REENTRANT_VARIABLES: &[Variable] =
+    &[Variable::new("YY_EXTRA_TYPE", "yyextra").comment("the data given to yylex_init_extra")];
+
+pub const BUFFER_VARIABLES: &[Variable] = &[
+    Variable::new("FILE *", "yyin").value("NULL"),
+    Variable::new("FILE *", "yyout").value("NULL"),
+    Variable::new("YY_BUFFER_STATE *", "g_buffer_stack")
+        .value("NULL")
+        .comment("the buffers pushed, the current one on the top"),
+    Variable::new("IdxType", "g_buffer_stack_top").value("0"),
+    Variable::new("IdxType", "g_buffer_stack_size").value("0"),
+    Variable::new("char *", "g_buffer")
+        .value("NULL")
+        .comment("the state of the current buffer, loaded here while scanning it"),
+    Variable::new("char *", "g_buffer_ptr"),
+    Variable::new("IdxType", "g_bufsize"),
+    Variable::new("IdxType", "g_buflen"),
+    Variable::new("int", "g_eof"),
+    Variable::new("int", "g_at_bol").value("1"),
+    Variable::new("int", "g_start_condition").value("0"),
+    Variable::new("int", "g_more")
+        .value("0")
+        .comment("yymore keeps the previous token in front of the next one"),
+    Variable::new("IdxType", "g_more_len").value("0"),
+    Variable::new("YY_BUFFER_STATE", "g_yyin_buffer")
+        .value("NULL")
+        .comment("the buffer yylex created to read yyin"),
+];
+
+pub const BUFFER: &str = r#"#define YY_CURRENT_BUFFER \
+  (g_buffer_stack ? g_buffer_stack[g_buffer_stack_top] : NULL)
+#define YY_CURRENT_BUFFER_LVALUE g_buffer_stack[g_buffer_stack_top]
+
+/* switch to a start condition, as BEGIN(COMMENT) or BEGIN COMMENT */
+#define BEGIN g_start_condition =
+/* the current start condition */
+#define YY_START g_start_condition
+#define YYSTATE YY_START
+
+static void yy_restore_yytext(YY_ONLY_ARG);
+
+static void yy_ensure_buffer_stack(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (g_buffer_stack == NULL) {
+    g_buffer_stack_size = 1;
+    g_buffer_stack = calloc(g_buffer_stack_size, sizeof(YY_BUFFER_STATE));
+  } else if (g_buffer_stack_top + 1 >= g_buffer_stack_size) {
+    g_buffer_stack_size *= 2;
+    g_buffer_stack = realloc(g_buffer_stack,
+                             g_buffer_stack_size * sizeof(YY_BUFFER_STATE));
   }
+  if (!g_buffer_stack)
+    YY_FATAL_ERROR("out of memory expanding the buffer stack");
 }
 
-/*
- * ============================================================================
- * builder/lexer.c - Lexer for regular expressions
- * ============================================================================
- */
-
-/*
- * Lexer
- */
-
-typedef enum TokenType {
-  LPAREN,
-  RPAREN,
-  LBRACKET,
-  RBRACKET,
-  CARET,
-  DASH,
-  DOT,
-  PLUS,
-  ASTERISK,
-  BAR,
-  BACK_SLASH,
-  LITERAL,
-  END
-} TokenType;
-
-typedef struct Token {
-  TokenType type;
-  char value; /* valid only for LITERAL type */
-} Token;
-
-/* create a token */
-Token *new_token(TokenType type, char value) {
-  Token *token = (Token *)malloc(sizeof(Token));
-  token->type = type;
-  token->value = value;
-  return token;
+/* keep the state of the current buffer in it */
+static void yy_save_buffer_state(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  YY_BUFFER_STATE buffer = YY_CURRENT_BUFFER;
+  if (buffer == NULL)
+    return;
+  yy_restore_yytext(YY_CALL_ONLY_ARG);
+  buffer->base = g_buffer;
+  buffer->pos = g_buffer_ptr;
+  buffer->size = g_bufsize;
+  buffer->len = g_buflen;
+  buffer->eof = g_eof;
+  buffer->at_bol = g_at_bol;
 }
 
-typedef struct Lexer {
-  char *pattern;
-  char *current_char;
-  Token *current_token;
-} Lexer;
-
-/* create a new lexer from pattern string */
-Lexer *new_lexer(char *pattern) {
-  Lexer *lexer = (Lexer *)malloc(sizeof(Lexer));
-  lexer->pattern = pattern;
-  lexer->current_char = lexer->pattern;
-  lexer->current_token = NULL;
-  return lexer;
+/* scan on from the state kept in the current buffer */
+static void yy_load_buffer_state(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  YY_BUFFER_STATE buffer = YY_CURRENT_BUFFER;
+  yyin = buffer->input;
+  g_buffer = buffer->base;
+  g_buffer_ptr = buffer->pos;
+  g_bufsize = buffer->size;
+  g_buflen = buffer->len;
+  g_eof = buffer->eof;
+  g_at_bol = buffer->at_bol;
+  g_more_len = 0;
 }
 
-Token *get_next_token(Lexer *lexer) {
-  if (lexer->current_token != NULL)
-    free(lexer->current_token);
-
-  char current_char = *lexer->current_char;
-  switch (current_char) {
-  case '\0':
-    lexer->current_token = new_token(END, '\0');
-    break;
-  case '(':
-    lexer->current_token = new_token(LPAREN, '(');
-    break;
-  case ')':
-    lexer->current_token = new_token(RPAREN, ')');
-    break;
-  case '[':
-    lexer->current_token = new_token(LBRACKET, '[');
-    break;
-  case ']':
-    lexer->current_token = new_token(RBRACKET, ']');
-    break;
-  case '^':
-    lexer->current_token = new_token(CARET, '^');
-    break;
-  case '-':
-    lexer->current_token = new_token(DASH, '-');
-    break;
-  case '.':
-    lexer->current_token = new_token(DOT, '.');
-    break;
-  case '+':
-    lexer->current_token = new_token(PLUS, '+');
-    break;
-  case '*':
-    lexer->current_token = new_token(ASTERISK, '*');
-    break;
-  case '|':
-    lexer->current_token = new_token(BAR, '|');
-    break;
-  case '\\':
-    lexer->current_token = new_token(BACK_SLASH, '\\');
-    break;
-  default:
-    lexer->current_token = new_token(LITERAL, current_char);
-    break;
-  }
-  ++(lexer->current_char);
-
-  return lexer->current_token;
+/* start reading the file from the beginning of the buffer */
+static void yy_init_buffer(YY_BUFFER_STATE buffer, FILE *file) {
+  buffer->input = file;
+#if defined YY_ALWAYS_INTERACTIVE
+  buffer->interactive = 1;
+#elif defined YY_NEVER_INTERACTIVE || defined YY_NO_UNISTD_H
+  buffer->interactive = 0;
+#else
+  buffer->interactive = file != NULL && isatty(fileno(file)) > 0;
+#endif
+  buffer->pos = buffer->base;
+  buffer->len = 0;
+  buffer->eof = 0;
+  buffer->at_bol = 1;
 }
 
-/*
- * ============================================================================
- * builder/ast.c - Abstract Syntax Tree
- * ============================================================================
- */
-
-typedef enum AstType {
-  LiteralNode,
-  SetNode,
-  AndNode,
-  OrNode,
-  RepeatNode,
-  SurroundNode,
-} AstType;
-
-typedef struct Ast Ast;
-typedef struct Vector_char Vector_char;
-
-typedef struct Ast {
-  enum { AstLiteral, AstSet, AstAnd, AstOr, AstRepeat, AstSurround } type;
-
-  union {
-    struct AstLiteral {
-      char value;
-    } AstLiteral;
-
-    struct AstSet {
-      Vector_char *set;
-      bool is_neg;
-    } AstSet;
-
-    struct AstAnd {
-      Ast *r1;
-      Ast *r2;
-    } AstAnd;
-
-    struct AstOr {
-      Ast *r1;
-      Ast *r2;
-    } AstOr;
-
-    struct AstRepeat {
-      Ast *r;
-    } AstRepeat;
-
-    struct AstSurround {
-      Ast *r;
-    } AstSurround;
-  } data;
-} Ast;
-
-Ast *new_ast(Ast ast) {
-  Ast *p = malloc(sizeof(Ast));
-  if (p != NULL)
-    *p = ast;
-  return p;
+/* a buffer reading the file, size bytes at a time */
+YY_BUFFER_STATE yy_create_buffer(FILE *file, int size YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  YY_BUFFER_STATE buffer = malloc(sizeof(struct yy_buffer_state));
+  if (!buffer)
+    YY_FATAL_ERROR("out of memory allocating a buffer in yy_create_buffer()");
+  buffer->size = size;
+  /* one more byte to terminate yytext at the end */
+  buffer->base = malloc(buffer->size + 1);
+  if (!buffer->base)
+    YY_FATAL_ERROR("out of memory allocating a buffer in yy_create_buffer()");
+  buffer->owned = 1;
+  yy_init_buffer(buffer, file);
+  return buffer;
 }
 
-#define NEW_AST(tag, ...)                                                      \
-  new_ast((Ast){tag, {.tag = (struct tag){__VA_ARGS__}}})
-
-/* newer */
-static Ast *new_ast_literal(char value) { return NEW_AST(AstLiteral, value); }
-
-static Ast *new_ast_set(Vector_char *set, bool is_neg) {
-  return NEW_AST(AstSet, set, is_neg);
+/* scan the buffer from where it was left */
+void yy_switch_to_buffer(YY_BUFFER_STATE buffer YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  yy_ensure_buffer_stack(YY_CALL_ONLY_ARG);
+  if (YY_CURRENT_BUFFER == buffer)
+    return;
+  yy_save_buffer_state(YY_CALL_ONLY_ARG);
+  YY_CURRENT_BUFFER_LVALUE = buffer;
+  yy_load_buffer_state(YY_CALL_ONLY_ARG);
 }
 
-static Ast *new_ast_and(Ast *r1, Ast *r2) { return NEW_AST(AstAnd, r1, r2); }
-
-static Ast *new_ast_or(Ast *r1, Ast *r2) { return NEW_AST(AstOr, r1, r2); }
-
-static Ast *new_ast_repeat(Ast *r) { return NEW_AST(AstRepeat, r); }
-
-static Ast *new_ast_surround(Ast *r) { return NEW_AST(AstSurround, r); }
-
-/* clone */
-static Ast *clone_ast(Ast *r) {
-  if (r == NULL)
-    return NULL;
-  switch (r->type) {
-  case LiteralNode:
-    return new_ast_literal(r->data.AstLiteral.value);
-  case SetNode:
-    return new_ast_set(r->data.AstSet.set, r->data.AstSet.is_neg);
-  case AndNode:
-    return new_ast_and(clone_ast(r->data.AstAnd.r1),
-                       clone_ast(r->data.AstAnd.r2));
-  case OrNode:
-    return new_ast_or(clone_ast(r->data.AstOr.r1), clone_ast(r->data.AstOr.r2));
-  case RepeatNode:
-    return new_ast_repeat(clone_ast(r->data.AstRepeat.r));
-  case SurroundNode:
-    return clone_ast(clone_ast(r->data.AstSurround.r));
+/* free the buffer, yylex reads yyin again if it was the current one */
+void yy_delete_buffer(YY_BUFFER_STATE buffer YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (buffer == NULL)
+    return;
+  if (buffer == YY_CURRENT_BUFFER) {
+    yy_save_buffer_state(YY_CALL_ONLY_ARG);
+    YY_CURRENT_BUFFER_LVALUE = NULL;
   }
-  return NULL; /* unreachable */
+  if (buffer == g_yyin_buffer)
+    g_yyin_buffer = NULL;
+  if (buffer->owned)
+    free(buffer->base);
+  free(buffer);
 }
 
-/* comparison */
-bool equal_ast(Ast *a, Ast *b) {
-  if (a == b)
-    return true;
-  if (a == NULL || b == NULL)
-    return false;
-  if (a->type != b->type)
-    return false;
-
-  switch (a->type) {
-  case LiteralNode:
-    return a->data.AstLiteral.value == b->data.AstLiteral.value;
-  case SetNode:
-    return true; /* ignore it */
-  case AndNode:
-    return equal_ast(a->data.AstAnd.r1, b->data.AstAnd.r1) &&
-           equal_ast(a->data.AstAnd.r2, b->data.AstAnd.r2);
-  case OrNode:
-    return equal_ast(a->data.AstOr.r1, b->data.AstOr.r1) &&
-           equal_ast(a->data.AstOr.r2, b->data.AstOr.r2);
-  case RepeatNode:
-    return equal_ast(a->data.AstRepeat.r, b->data.AstRepeat.r);
-  case SurroundNode:
-    return equal_ast(a->data.AstSurround.r, b->data.AstSurround.r);
-  default:
-    return false;
-  }
+/* scan the buffer until it is popped, then go on with the current one */
+void yypush_buffer_state(YY_BUFFER_STATE buffer YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (buffer == NULL)
+    return;
+  yy_ensure_buffer_stack(YY_CALL_ONLY_ARG);
+  yy_save_buffer_state(YY_CALL_ONLY_ARG);
+  if (YY_CURRENT_BUFFER)
+    ++g_buffer_stack_top;
+  YY_CURRENT_BUFFER_LVALUE = buffer;
+  yy_load_buffer_state(YY_CALL_ONLY_ARG);
 }
 
-/* free */
-void free_ast(Ast *node) {
-  if (node == NULL)
+/* delete the current buffer, and go on with the one pushed before */
+void yypop_buffer_state(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (YY_CURRENT_BUFFER == NULL)
     return;
-  switch (node->type) {
-  case LiteralNode:
-    /* nothing to free */
-    break;
-  case SetNode:
-    /* nothing to free */
-    break;
-  case AndNode:
-    free_ast(node->data.AstAnd.r1);
-    free_ast(node->data.AstAnd.r2);
-    break;
-  case OrNode:
-    free_ast(node->data.AstOr.r1);
-    free_ast(node->data.AstOr.r2);
-    break;
-  case RepeatNode:
-    free_ast(node->data.AstRepeat.r);
-    break;
-  case SurroundNode:
-    free_ast(node->data.AstSurround.r);
-    break;
+  yy_delete_buffer(YY_CURRENT_BUFFER YY_CALL_LAST_ARG);
+  if (g_buffer_stack_top > 0) {
+    --g_buffer_stack_top;
+    yy_load_buffer_state(YY_CALL_ONLY_ARG);
   }
-  free(node);
 }
 
-/*
- * ============================================================================
- * builder/parser.c - Parser for regular expressions
- * ============================================================================
- */
-
-
-/* pre-define */
-Vector_char *new_vector_char();
-int push_vector_char(Vector_char *vec, char value);
-
-typedef struct Parser {
-  Lexer *lexer;
-  Token *current_token;
-} Parser;
-
-Parser *new_parser(Lexer *lexer) {
-  Parser *parser = (Parser *)malloc(sizeof(Parser));
-  parser->lexer = lexer;
-  parser->current_token = get_next_token(lexer);
-  return parser;
-}
-
-static void eat(Parser *parser, TokenType type) {
-  if (parser->current_token->type == type) {
-    parser->current_token = get_next_token(parser->lexer);
+/* scan the file from its current position with the current buffer */
+void yyrestart(FILE *file YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (YY_CURRENT_BUFFER == NULL) {
+    yy_ensure_buffer_stack(YY_CALL_ONLY_ARG);
+    YY_CURRENT_BUFFER_LVALUE =
+        yy_create_buffer(file, YY_BUF_SIZE YY_CALL_LAST_ARG);
   } else {
-    printf("Wrong Token Type! Expect %d, found %d.\n", type,
-           parser->current_token->type);
-    exit(1);
+    yy_save_buffer_state(YY_CALL_ONLY_ARG);
+    yy_init_buffer(YY_CURRENT_BUFFER, file);
   }
+  yy_load_buffer_state(YY_CALL_ONLY_ARG);
 }
 
-static char eat_escape_char(Parser *parser) {
-  eat(parser, BACK_SLASH);
-  char value;
-  switch (parser->current_token->value) {
-  case 'a': /* beep */
-    value = '\a';
-    break;
-  case 'n': /* newline */
-    value = '\n';
-    break;
-  case 'r': /* carriage return */
-    value = '\r';
-    break;
-  case 't': /* tab */
-    value = '\t';
-    break;
-  default:
-    value = parser->current_token->value;
-  }
-  parser->current_token = get_next_token(parser->lexer);
-  return value;
+/* at the end of the input of yylex, the next call reads yyin again */
+static void yy_end_of_scan(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (g_yyin_buffer != NULL && g_yyin_buffer == YY_CURRENT_BUFFER && g_eof &&
+      g_buffer_ptr == g_buffer + g_buflen)
+    yy_delete_buffer(g_yyin_buffer YY_CALL_LAST_ARG);
 }
 
-/* Forward declarations */
-static Ast *parse_expr(Parser *parser);
-static Ast *parse_term(Parser *parser);
-static Ast *parse_factor(Parser *parser);
-static Ast *parse_base(Parser *parser);
-static Ast *parse_range(Parser *parser);
+/* stop scanning and return 0 from yylex */
+#define YY_NULL 0
+#define yyterminate() return (yy_end_of_scan(YY_CALL_ONLY_ARG), YY_NULL)
 
 /*
- * expr := term ('|' term)*
+ * make sure the character at offset from g_buffer_ptr is in the buffer, the
+ * consumed input except the text kept by yymore is dropped before reading
+ * more. return 0 at the end of input
  */
-static Ast *parse_expr(Parser *parser) {
-  Ast *node = parse_term(parser);
-  while (parser->current_token->type == BAR) {
-    eat(parser, BAR);
-    Ast *right = parse_term(parser);
-    node = new_ast_or(node, right);
+static int yy_fill_buffer(IdxType offset YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  while (g_buffer_ptr + offset >= g_buffer + g_buflen) {
+    if (g_eof)
+      return 0;
+
+    IdxType kept = g_buffer + g_buflen - g_buffer_ptr + g_more_len;
+    memmove(g_buffer, g_buffer_ptr - g_more_len, kept);
+    g_buflen = kept;
+    /* a token longer than the buffer */
+    if (g_buflen == g_bufsize) {
+      if (!YY_CURRENT_BUFFER->owned)
+        YY_FATAL_ERROR("input buffer overflow, can't enlarge a buffer given to yy_scan_buffer()");
+      g_bufsize *= 2;
+      /* one more byte to terminate yytext at the end */
+      g_buffer = realloc(g_buffer, g_bufsize + 1);
+      if (!g_buffer)
+        YY_FATAL_ERROR("out of memory expanding the input buffer");
+    }
+    g_buffer_ptr = g_buffer + g_more_len;
+
+    IdxType count;
+    YY_INPUT(g_buffer + g_buflen, count, g_bufsize - g_buflen);
+    if (count == 0)
+      g_eof = 1;
+    g_buflen += count;
   }
-  return node;
+  return 1;
 }
 
-/*
- * term := factor*
- */
-static Ast *parse_term(Parser *parser) {
-  Ast *node = parse_factor(parser);
-  while (parser->current_token->type == LITERAL ||
-         parser->current_token->type == CARET ||
-         parser->current_token->type == DOT ||
-         parser->current_token->type == LBRACKET ||
-         parser->current_token->type == LPAREN ||
-         parser->current_token->type == BACK_SLASH) {
-    Ast *right = parse_factor(parser);
-    node = new_ast_and(node, right);
-  }
-  return node;
+/* scan the first size - 2 bytes of base in place, the last two must be 0 */
+YY_BUFFER_STATE yy_scan_buffer(char *base, IdxType size YY_LAST_ARG) {
+  if (size < 2 || base[size - 2] != '\0' || base[size - 1] != '\0')
+    return NULL;
+  YY_BUFFER_STATE buffer = malloc(sizeof(struct yy_buffer_state));
+  if (!buffer)
+    YY_FATAL_ERROR("out of memory allocating a buffer in yy_scan_buffer()");
+  buffer->input = NULL;
+  buffer->base = buffer->pos = base;
+  buffer->size = buffer->len = size - 2;
+  buffer->owned = 0;
+  buffer->interactive = 0;
+  buffer->eof = 1;
+  buffer->at_bol = 1;
+  yy_switch_to_buffer(buffer YY_CALL_LAST_ARG);
+  return buffer;
 }
 
-/*
- * factor := base '*'
- *           base '+'
- */
-static Ast *parse_factor(Parser *parser) {
-  Ast *node = parse_base(parser);
-  if (parser->current_token->type == ASTERISK) {
-    eat(parser, ASTERISK);
-    node = new_ast_repeat(node);
-  } else if (parser->current_token->type == PLUS) {
-    eat(parser, PLUS);
-    node = new_ast_and(clone_ast(node), new_ast_repeat(node));
-  }
-  return node;
+/* scan a copy of len bytes */
+YY_BUFFER_STATE yy_scan_bytes(const char *bytes, IdxType len YY_LAST_ARG) {
+  char *base = malloc(len + 2);
+  if (!base)
+    YY_FATAL_ERROR("out of memory allocating a buffer in yy_scan_bytes()");
+  memcpy(base, bytes, len);
+  base[len] = base[len + 1] = '\0';
+  YY_BUFFER_STATE buffer = yy_scan_buffer(base, len + 2 YY_CALL_LAST_ARG);
+  buffer->owned = 1;
+  return buffer;
 }
 
-/*
- * base := LITERAL | CARET
- *       | BACK_SLASH any_single_character
- *       | DOT
- *       | '[' range ']'
- *       | '(' expr ')'
- */
-static Ast *parse_base(Parser *parser) {
-  switch (parser->current_token->type) {
-  case LITERAL: {
-    char value = parser->current_token->value;
-    eat(parser, LITERAL);
-    return new_ast_literal(value);
-  }
-  case CARET: {
-    char value = parser->current_token->value;
-    eat(parser, CARET);
-    return new_ast_literal(value);
-  }
-  case BACK_SLASH: {
-    return new_ast_literal(eat_escape_char(parser));
-  }
-  case DOT: {
-    /* anything but newline */
-    eat(parser, DOT);
-    Vector_char *set = new_vector_char();
-    push_vector_char(set, '\n');
-    return new_ast_set(set, true);
-  }
-  case LBRACKET: {
-    eat(parser, LBRACKET);
-    Ast *node = parse_range(parser);
-    eat(parser, RBRACKET);
-    return node;
-  }
-  case LPAREN: {
-    eat(parser, LPAREN);
-    Ast *node = parse_expr(parser);
-    eat(parser, RPAREN);
-    return new_ast_surround(node);
-  }
-  default: {
-    printf("unexpected token: %d\n", parser->current_token->type);
-    exit(1);
-  }
-  }
+/* scan a copy of the string */
+YY_BUFFER_STATE yy_scan_string(const char *str YY_LAST_ARG) {
+  return yy_scan_bytes(str, strlen(str) YY_CALL_LAST_ARG);
 }
 
-/*
- * range or set
- * range := CARET
- *        | LITERAL
- *        | BACK_SLASH any_single_character
- *        | LITERAL DASH LITERAL
- *        | range
- */
-static Ast *parse_range(Parser *parser) {
-  /* parse negate ^ */
-  bool is_neg = false;
-  if (parser->current_token->type == CARET) {
-    is_neg = true;
-    eat(parser, CARET);
-  }
+"#;
 
-  Vector_char *set = new_vector_char();
-  while (parser->current_token->type != RBRACKET) {
-    if (parser->current_token->type == BACK_SLASH) {
-      push_vector_char(set, eat_escape_char(parser));
-    } else {
-      char from = parser->current_token->value;
-      eat(parser, LITERAL);
-      Ast *right;
-      /* range */
-      if (parser->current_token->type == DASH) {
-        eat(parser, DASH);
-        char to = parser->current_token->value;
-        eat(parser, LITERAL);
-        for (char c = from; c <= to; ++c)
-          push_vector_char(set, c);
-      }
-      /* set */
-      else
-        push_vector_char(set, from);
-    }
-  }
-  return new_ast_set(set, is_neg);
+pub const YYTEXT_POINTER_VARIABLES: &[Variable] = &[
+    Variable::new("char *", "yytext")
+        .comment("yytext points into the buffer, the character after it is held"),
+    Variable::new("IdxType", "yyleng"),
+    Variable::new("char *", "g_hold_ptr").value("NULL"),
+    Variable::new("char", "g_hold_char"),
+];
+
+pub const YYTEXT_POINTER: &str = r#"/* the token is the yyleng characters before g_buffer_ptr */
+static void yy_set_yytext(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  yytext = g_buffer_ptr - yyleng;
+  g_hold_ptr = g_buffer_ptr;
+  g_hold_char = *g_hold_ptr;
+  *g_hold_ptr = '\0';
 }
 
-/* Entry point for parsing */
-Ast *parse(Parser *parser) {
-  Ast *node = parse_expr(parser);
-  if (parser->current_token->type != END) {
-    printf("unexpected trailing token: %d\n", parser->current_token->type);
-    exit(1);
+/* put the held character back before scanning on */
+static void yy_restore_yytext(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (g_hold_ptr) {
+    *g_hold_ptr = g_hold_char;
+    g_hold_ptr = NULL;
   }
-  return node;
 }
 
-/*
- * ============================================================================
- * edge.c - Edges between states
- * ============================================================================
- */
-
-#define TYPE char
-/*
- * create new, push, free functions for TYPE
- * usage:
- *   #define TYPE int
- *   #include <this_file>
- */
+"#;
 
-#ifndef TYPE
-#error "define TYPE first"
-#else
+pub const YYTEXT_ARRAY_VARIABLES: &[Variable] = &[
+    Variable::new("char", "yytext")
+        .size("YYLMAX")
+        .comment("yytext is a copy of the token"),
+    Variable::new("IdxType", "yyleng"),
+];
+
+pub const YYTEXT_ARRAY: &str = r#"static void yy_set_yytext(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (yyleng >= YYLMAX)
+    YY_FATAL_ERROR("token too large, exceeds YYLMAX");
+  memcpy(yytext, g_buffer_ptr - yyleng, yyleng);
+  yytext[yyleng] = '\0';
+}
 
-#define CAT1(a, b) a##b
-#define CAT(a, b) CAT1(a, b)
-#define APPEND_TYPE(a) CAT(CAT(a, _), TYPE)
-#define TYPE_NAME APPEND_TYPE(Vector)
+static void yy_restore_yytext(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+}
 
-#include <stddef.h>
-#include <stdlib.h>
+"#;
 
-typedef struct TYPE_NAME {
-  TYPE *data;
-  size_t size;
-  size_t capacity;
-} TYPE_NAME;
+pub const NEXT_STATE_FULL: &str = r#"#define yy_next_state(state, c) (g_transitions[state][c])
+"#;
 
-/* create a new vector */
-TYPE_NAME *APPEND_TYPE(new_vector)() {
-  TYPE_NAME *vec = (TYPE_NAME *)malloc(sizeof(TYPE_NAME));
-  if (!vec)
-    return NULL;
-  vec->size = 0;
-  vec->capacity = 4;
-  vec->data = (TYPE *)malloc(vec->capacity * sizeof(TYPE));
-  if (!vec->data) {
-    free(vec);
-    return NULL;
-  }
-  return vec;
+pub const NEXT_STATE_COMPRESSED: &str = r#"/* follow the default states until the transition is stored */
+static int yy_next_state(int state, unsigned char c) {
+  int class = g_ec[c];
+  while (state != YY_DEAD_STATE && g_check[g_base[state] + class] != state)
+    state = g_default[state];
+  if (state == YY_DEAD_STATE)
+    return YY_DEAD_STATE;
+  return g_next[g_base[state] + class];
 }
+"#;
 
-/* push a value to the vector */
-int APPEND_TYPE(push_vector)(TYPE_NAME *vec, TYPE value) {
-  if (vec->size >= vec->capacity) {
-    size_t new_capacity = vec->capacity * 2;
-    TYPE *new_data = (TYPE *)realloc(vec->data, new_capacity * sizeof(TYPE));
-    if (!new_data)
-      return -1;
-    vec->data = new_data;
-    vec->capacity = new_capacity;
+pub const STATE_BUF_VARIABLES: &[Variable] = &[
+    Variable::new("int *", "g_state_buf").value("NULL"),
+    Variable::new("IdxType", "g_state_buf_size").value("0"),
+];
+
+pub const STATE_BUF: &str = r#"/* remember the state after len characters of the current match */
+static void yy_record_state(IdxType len, int state YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (len >= g_state_buf_size) {
+    g_state_buf_size = len * 2 + 64;
+    g_state_buf = realloc(g_state_buf, g_state_buf_size * sizeof(int));
+    if (!g_state_buf)
+      YY_FATAL_ERROR("out of memory expanding the state buffer");
   }
-  vec->data[vec->size++] = value;
-  return 0;
+  g_state_buf[len] = state;
 }
 
-/* free the vector */
-void APPEND_TYPE(free_vector)(TYPE_NAME *vec) {
-  if (vec) {
-    free(vec->data);
-    free(vec);
+"#;
+
+pub const HEAD_END: &str = r#"/* the length of the longest head of the pattern within len characters */
+static IdxType yy_head_end(int pattern_index, IdxType len YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  for (; len > 0; --len) {
+    int state = g_state_buf[len];
+    for (int i = g_head_end_bases[state]; i < g_head_end_bases[state + 1]; ++i)
+      if (g_head_ends[i] == pattern_index)
+        return len;
   }
+  return 0;
 }
 
-#undef TYPE
-#endif /* ifndef TYPE */
-
-typedef struct Label {
-  enum {
-    CHAR,
-    SET,
-    NEG_SET,
-  } type;
-
-  union {
-    char symbol;
-    Vector_char *set;
-  } data;
-} Label;
-
-Label *new_literal_label(char symbol) {
-  Label *label = (Label *)malloc(sizeof(Label));
-  label->type = CHAR;
-  label->data.symbol = symbol;
-  return label;
-}
+"#;
 
-Label *new_set_label(Vector_char *set, bool is_neg) {
-  Label *label = (Label *)malloc(sizeof(Label));
-  label->type = (is_neg ? NEG_SET : SET);
-  label->data.set = set;
-  return label;
-}
+pub const LINENO_VARIABLES: &[Variable] = &[Variable::new("int", "yylineno").value("1")];
 
-typedef struct Edge {
-  Label *label;
-  State from;
-  State to;
-} Edge;
-
-/* create a new edge */
-Edge *new_edge(Label *label, State from, State to) {
-  Edge *e = (Edge *)malloc(sizeof(Edge));
-  e->label = label;
-  e->from = from;
-  e->to = to;
-  return e;
+pub const LINENO: &str = r#"/* count the lines in the tokens of the rules which can match a newline */
+static void yy_update_lineno(int pattern_index YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (!g_newline_rules[pattern_index])
+    return;
+  for (IdxType i = g_more_len; i < yyleng; ++i)
+    if (yytext[i] == '\n')
+      ++yylineno;
 }
 
-/*
- * ============================================================================
- * nfa.c - NFA (Non-deterministic Finite Automaton) implementation
- * ============================================================================
- */
-
-char EPSILON = -1;
-
-typedef struct NFA {
-  State states_count;
-  States *target_states;
-  Edge *edges[MAX];
-  unsigned int edges_count;
-} NFA;
-
-/* create a new NFA */
-NFA *new_nfa() {
-  NFA *nfa = (NFA *)malloc(sizeof(NFA));
-  nfa->states_count = 0;
-  nfa->target_states = NULL;
-  nfa->edges_count = 0;
-  return nfa;
+#ifdef YY_USES_REJECT
+/* take the lines of a rejected token back */
+static void yy_reset_lineno(int pattern_index YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  if (!g_newline_rules[pattern_index])
+    return;
+  for (IdxType i = g_more_len; i < yyleng; ++i)
+    if (yytext[i] == '\n')
+      --yylineno;
 }
+#endif
 
-/* set the states count of an NFA */
-void set_states_count(NFA *nfa, State states_count) {
-  nfa->states_count = states_count;
-}
+"#;
 
-/* set the target states of an NFA */
-void set_target_states(NFA *nfa, States *s) { nfa->target_states = s; }
+pub const LOCATION_TYPE: &str = r#"/* where the current token is, lines and columns start from 1 */
+#ifndef YY_STRUCT_YY_LOCATION
+#define YY_STRUCT_YY_LOCATION
+struct yy_location {
+  int first_line;
+  int first_column;
+  int last_line;
+  int last_column;
+  /* the number of bytes before the token */
+  IdxType offset;
+};
+#endif
 
-/* add an edge to an NFA */
-void push_edge(NFA *nfa, Edge *e) {
-  nfa->edges[nfa->edges_count] = e;
-  ++(nfa->edges_count);
-}
+"#;
 
-/* print edges in the form of `from --symbol--> to` */
-void print_edges(NFA *nfa) {
-  printf("=== NFA\n");
-  for (size_t i = 0; i < nfa->edges_count; ++i) {
-    Edge *e = nfa->edges[i];
-    Label *l = e->label;
-    if (l->type == CHAR) {
-      char symbol = l->data.symbol;
-      if (symbol == EPSILON)
-        printf("%2d ---ε---> %2d\n", e->from, e->to);
-      else
-        printf("%2d ---%c---> %2d\n", e->from, symbol, e->to);
-    } else if (l->type == SET || NEG_SET) {
-      printf("%2d --", e->from);
-      if (l->type == NEG_SET)
-        printf("^");
-      for (size_t i = 0; i < e->label->data.set->size; ++i) {
-        char c = e->label->data.set->data[i];
-        if (c == '\n')
-          printf("↵");
-        else
-          printf("%c", c);
-      }
-      printf("--> %2d\n", e->to);
+pub const LOCATION_VARIABLES: &[Variable] = &[
+    Variable::new("int", "yylineno").value("1"),
+    Variable::new("struct yy_location", "yylocation").value("{1, 1, 1, 0, 0}"),
+    Variable::new("int", "g_column").value("1"),
+    Variable::new("IdxType", "g_offset").value("0"),
+];
+
+pub const LOCATION: &str = r#"/* move the location past the current token */
+static void yy_update_lineno(int pattern_index YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  /* every token moves the column, not only the ones with a newline */
+  (void)pattern_index;
+  yylocation.first_line = yylineno;
+  yylocation.first_column = g_column;
+  yylocation.offset = g_offset;
+  for (IdxType i = g_more_len; i < yyleng; ++i) {
+    yylocation.last_line = yylineno;
+    yylocation.last_column = g_column;
+    if (yytext[i] == '\n') {
+      ++yylineno;
+      g_column = 1;
+    } else {
+      ++g_column;
     }
   }
+  g_offset += yyleng - g_more_len;
 }
 
-/* free an NFA */
-void free_nfa(NFA *nfa) {
-  /* free edges */
-  for (size_t i = 0; i < nfa->edges_count; ++i) {
-    free(nfa->edges[i]->label);
-    free(nfa->edges[i]);
-  }
-  /* free target states */
-  if (nfa->target_states != NULL) {
-    free(nfa->target_states);
-  }
-  /* free nfa */
-  free(nfa);
-  nfa = NULL;
+#ifdef YY_USES_REJECT
+/* move the location back before a rejected token */
+static void yy_reset_lineno(int pattern_index YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  (void)pattern_index;
+  yylineno = yylocation.first_line;
+  g_column = yylocation.first_column;
+  g_offset = yylocation.offset;
 }
+#endif
 
-static bool accept(Label *label, char input) {
-  if (input == EPSILON)
-    return label->type == CHAR && label->data.symbol == EPSILON;
-
-  switch (label->type) {
-  case CHAR:
-    return input == label->data.symbol;
-  case SET:
-    for (size_t i = 0; i < label->data.set->size; ++i)
-      if (label->data.set->data[i] == input)
-        return true;
-    return false;
-  case NEG_SET:
-    for (size_t i = 0; i < label->data.set->size; ++i)
-      if (label->data.set->data[i] == input)
-        return false;
-    return true;
-  }
-  exit(EXIT_FAILURE);
-}
+"#;
 
-/* return all states reachable with epsilon labels from the given states */
-States *epsilon_closure(NFA *nfa, States *s) {
-  States *new_s = new_states();
+pub const BISON_YYSTYPE: &str = r#"/* the semantic value type, unless the bison header declares it */
+#if !defined YYSTYPE && !defined YYSTYPE_IS_DECLARED
+typedef int YYSTYPE;
+#define YYSTYPE_IS_DECLARED 1
+#endif
+"#;
 
-  /* add all original states to the closure first */
-  for (size_t i = 0; i < s->len; ++i) {
-    push_state(new_s, s->states[i]);
-  }
+pub const BISON_YYLTYPE: &str = r#"/* the location type, unless the bison header declares it */
+#if !defined YYLTYPE && !defined YYLTYPE_IS_DECLARED
+typedef struct YYLTYPE {
+  int first_line;
+  int first_column;
+  int last_line;
+  int last_column;
+} YYLTYPE;
+#define YYLTYPE_IS_DECLARED 1
+#endif
+"#;
 
-  /* simulate the original states as a stack */
-  while (s->len > 0) {
-    --(s->len);
-    State state = s->states[s->len];
-    for (size_t i = 0; i < nfa->edges_count; ++i) {
-      Edge *e = nfa->edges[i];
-      if (e->from == state && accept(e->label, EPSILON)) {
-        State next_state = e->to;
-        if (!have_state(new_s, next_state)) {
-          push_state(new_s, next_state);
-          s->states[s->len] = next_state;
-          ++(s->len);
-        }
-      }
+pub const REJECT_VARIABLES: &[Variable] = &[
+    Variable::new("char *", "g_match_start")
+        .comment("where the match started, and the next pattern to try after REJECT"),
+    Variable::new("IdxType", "g_reject_len"),
+    Variable::new("int", "g_reject_index"),
+];
+
+pub const REJECT: &str = r#"/*
+ * take the next pattern accepted in the recorded states, longer matches
+ * first, and the patterns listed first among the matches of the same length
+ */
+static int yy_next_match(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  for (; g_reject_len > 0; --g_reject_len, g_reject_index = 0) {
+    int state = g_state_buf[g_reject_len];
+    int begin = g_accept_bases[state];
+    int end = g_accept_bases[state + 1];
+#ifdef YY_USES_EOL
+    /* patterns ending with $ are only accepted before a newline or the end */
+    char *next = g_match_start + g_reject_len;
+    if (next == g_buffer + g_buflen || *next == '\n') {
+      begin = g_eol_accept_bases[state];
+      end = g_eol_accept_bases[state + 1];
+    }
+#endif
+    if (begin + g_reject_index < end) {
+      int pattern_index = g_accept_list[begin + g_reject_index++];
+      IdxType len = g_reject_len;
+#ifdef YY_USES_TRAILING_CONTEXT
+      len = yy_trailing_context(pattern_index, len YY_CALL_LAST_ARG);
+#endif
+      g_buffer_ptr = g_match_start + len;
+      yyleng = g_more_len + len;
+      yy_set_yytext(YY_CALL_ONLY_ARG);
+      if (yyleng > 0)
+        g_at_bol = yytext[yyleng - 1] == '\n';
+      return pattern_index;
     }
   }
+  YY_FATAL_ERROR("REJECT without another match");
+  return 0;
+}
 
-  free(s);
-  return new_s;
+/* give the current match back and take the next one */
+static int yy_reject(int pattern_index YY_LAST_ARG) {
+#ifdef YY_USES_LINENO
+  yy_reset_lineno(pattern_index YY_CALL_LAST_ARG);
+#else
+  (void)pattern_index;
+#endif
+  yy_restore_yytext(YY_CALL_ONLY_ARG);
+  return yy_next_match(YY_CALL_ONLY_ARG);
 }
 
-/* return all states reachable with given symbol from the given states */
-States *move(NFA *nfa, States *s, char symbol) {
-  States *new_s = new_states();
-  for (size_t i = 0; i < s->len; ++i) {
-    for (size_t j = 0; j < nfa->edges_count; ++j) {
-      Edge *e = nfa->edges[j];
-      if (e->from == s->states[i] && accept(e->label, symbol)) {
-        State next_state = e->to;
-        if (!have_state(new_s, next_state)) {
-          push_state(new_s, next_state);
-        }
-      }
-    }
+/* run the action of the next best match */
+#define REJECT                                                                 \
+  {                                                                            \
+    pattern_index = yy_reject(pattern_index YY_CALL_LAST_ARG);                 \
+    goto yy_find_action;                                                       \
   }
-  free(s);
-  return new_s;
-}
+"#;
 
+pub const MATCH: &str = r#"
 /*
- * ============================================================================
- * builder.c - Build an NFA from a string
- * ============================================================================
+ * run the DFA from g_buffer_ptr to find the longest match, copy it to yytext
+ * after the text kept by yymore, assign the length to yyleng, and return the
+ * index of the pattern matched
  */
+static int yy_match(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  int state = g_start_states[2 * g_start_condition + g_at_bol];
+  int last_accept = 0;
+  IdxType last_accept_len = 0;
+  IdxType len = 0;
+#ifdef YY_USES_STATE_BUF
+  yy_record_state(0, state YY_CALL_LAST_ARG);
+#endif
+
+  /* nothing more is read after a state the match cannot go on from */
+  while (!yy_dead_end(state) && yy_fill_buffer(len YY_CALL_LAST_ARG)) {
+    state = yy_next_state(state, (unsigned char)g_buffer_ptr[len]);
+    if (state == YY_DEAD_STATE)
+      break;
+    ++len;
+#ifdef YY_USES_STATE_BUF
+    yy_record_state(len, state YY_CALL_LAST_ARG);
+#endif
+
+#ifndef YY_USES_REJECT
+    int accept = g_accepts[state];
+#ifdef YY_USES_EOL
+    /* patterns ending with $ are only accepted before a newline or the end */
+    if (!yy_fill_buffer(len YY_CALL_LAST_ARG) || g_buffer_ptr[len] == '\n')
+      accept = g_eol_accepts[state];
+#endif
+    /* if any pattern is accepted, mark matching */
+    if (accept) {
+      last_accept = accept;
+      last_accept_len = len;
+    }
+#endif
+  }
 
-static State g_state_counts = 0;
-
-typedef struct {
-  NFA *nfa;
-  State start;
-  State accept;
-} NFAFragment;
-
-/* create a new NFA fragment */
-static NFAFragment *new_nfa_fragment(NFA *nfa, State start, State accept) {
-  NFAFragment *fragment = (NFAFragment *)malloc(sizeof(NFAFragment));
-  fragment->nfa = nfa;
-  fragment->start = start;
-  fragment->accept = accept;
-  return fragment;
-}
-
-/* get current state counts */
-static State get_state_counts() { return g_state_counts; }
-
-/* increase states counts and get the latest state number */
-static State increase_state_counts() { return g_state_counts++; }
-
-/* decrease states counts by one, used to concatenate two NFA */
-static void decrease_state_counts() { --g_state_counts; }
-
-/* add an ε-labled edge to the NFA */
-static void add_epsilon(NFA *nfa, State from, State to) {
-  push_edge(nfa, new_edge(new_literal_label(EPSILON), from, to));
-}
-
-/* add a symbol-labled edge to the NFA */
-static void add_symbol(NFA *nfa, State from, State to, char symbol) {
-  push_edge(nfa, new_edge(new_literal_label(symbol), from, to));
+#ifdef YY_USES_REJECT
+  /* the patterns accepted on the way are taken from the recorded states */
+  g_match_start = g_buffer_ptr;
+  g_reject_len = len;
+  g_reject_index = 0;
+  return yy_next_match(YY_CALL_ONLY_ARG);
+#endif
+
+#ifdef YY_USES_TRAILING_CONTEXT
+  /* the trailing context is left in the input */
+  last_accept_len =
+      yy_trailing_context(last_accept - 1, last_accept_len YY_CALL_LAST_ARG);
+#endif
+  g_buffer_ptr += last_accept_len;
+  yyleng = g_more_len + last_accept_len;
+  yy_set_yytext(YY_CALL_ONLY_ARG);
+  /* patterns starting with ^ are only tried at the beginning of a line */
+  if (yyleng > 0)
+    g_at_bol = yytext[yyleng - 1] == '\n';
+
+  /* the last pattern matches any character, so there is always a match */
+  return last_accept - 1;
 }
+"#;
 
-static void add_set(NFA *nfa, State from, State to, Vector_char *set,
-                    bool is_neg) {
-  push_edge(nfa, new_edge(new_set_label(set, is_neg), from, to));
+pub const ACTION_HELPERS: &str = r#"
+/* copy the token to yyout */
+#ifndef ECHO
+#define ECHO fwrite(yytext, (size_t)yyleng, 1, yyout)
+#endif
+
+/* keep the first n characters of the token, the rest is scanned again */
+#define yyless(n) yy_less(n YY_CALL_LAST_ARG)
+static YY_UNUSED void yy_less(IdxType n YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  yy_restore_yytext(YY_CALL_ONLY_ARG);
+  char *start = g_buffer_ptr - yyleng;
+#ifdef YY_USES_LINENO
+  for (IdxType i = n; i < yyleng; ++i)
+    if (start[i] == '\n')
+      --yylineno;
+#endif
+  if (g_more_len > n)
+    g_more_len = n;
+  g_buffer_ptr = start + n;
+  yyleng = n;
+  yy_set_yytext(YY_CALL_ONLY_ARG);
+  if (n > 0)
+    g_at_bol = start[n - 1] == '\n';
+  else if (start > g_buffer)
+    g_at_bol = start[-1] == '\n';
 }
 
-/* move all edges from source NFA to destination NFA */
-static void move_edges(NFA *dst, NFA *src) {
-  for (size_t i = 0; i < src->edges_count; ++i) {
-    push_edge(dst, src->edges[i]);
+/* append the next token to this one in yytext */
+#define yymore() (g_more = 1)
+
+#ifndef YY_NO_UNPUT
+/* push the character back to be scanned next, yytext may be changed */
+#define unput(c) yyunput(c YY_CALL_LAST_ARG)
+static YY_UNUSED void yyunput(int c YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  yy_restore_yytext(YY_CALL_ONLY_ARG);
+  g_more_len = 0;
+  if (g_buffer_ptr == g_buffer) {
+    /* make room in front of the input left */
+    if (g_buflen == g_bufsize) {
+      if (!YY_CURRENT_BUFFER->owned)
+        YY_FATAL_ERROR("can't unput into a buffer given to yy_scan_buffer()");
+      g_bufsize *= 2;
+      g_buffer = realloc(g_buffer, g_bufsize + 1);
+      if (!g_buffer)
+        YY_FATAL_ERROR("out of memory expanding the input buffer");
+    }
+    memmove(g_buffer + 1, g_buffer, g_buflen);
+    ++g_buflen;
+    g_buffer_ptr = g_buffer + 1;
   }
-  src->edges_count = 0;
+  *--g_buffer_ptr = (char)c;
+#ifdef YY_USES_LINENO
+  if (c == '\n')
+    --yylineno;
+#endif
 }
+#endif
+
+#ifndef YY_NO_INPUT
+/* read the next character of the input, 0 at the end of input */
+static YY_UNUSED int input(YY_ONLY_ARG) {
+  YY_DECL_GUTS_VAR();
+  yy_restore_yytext(YY_CALL_ONLY_ARG);
+  if (!yy_fill_buffer(0 YY_CALL_LAST_ARG))
+    return 0;
+  int c = (unsigned char)*g_buffer_ptr++;
+  g_at_bol = c == '\n';
+#ifdef YY_USES_LINENO
+  if (c == '\n')
+    ++yylineno;
+#endif
+  return c;
+}
+#endif
+"#;
 
-static NFAFragment *ast2nfa_fragment(Ast *ast) {
-  if (ast == NULL)
-    return NULL;
-
-  switch (ast->type) {
-  case LiteralNode: {
-    /* START --literal--> END */
-    NFA *nfa = new_nfa();
-    State start = increase_state_counts();
-    State accept = increase_state_counts();
-    add_symbol(nfa, start, accept, ast->data.AstLiteral.value);
-    return new_nfa_fragment(nfa, start, accept);
-  }
-
-  case SetNode: {
-    /* START --set--> END */
-    NFA *nfa = new_nfa();
-    State start = increase_state_counts();
-    State accept = increase_state_counts();
-    add_set(nfa, start, accept, ast->data.AstSet.set, ast->data.AstSet.is_neg);
-    return new_nfa_fragment(nfa, start, accept);
-  }
-
-  case AndNode: {
-    /* START --left--> (left end & right start) --right--> END */
-    NFAFragment *left = ast2nfa_fragment(ast->data.AstAnd.r1);
-    decrease_state_counts(); /* concatenate left end and right start */
-    NFAFragment *right = ast2nfa_fragment(ast->data.AstAnd.r2);
-    move_edges(left->nfa, right->nfa);
-    free(right->nfa);
-    NFAFragment *result =
-        new_nfa_fragment(left->nfa, left->start, right->accept);
-    free(left);
-    free(right);
-    return result;
-  }
-
-  case OrNode: {
-    /*
-     *          /-ε--> S₀ --left---> S₁ -ε-\
-     * START --<                            >--> END
-     *          \-ε--> S₂ --right--> S₃ -ε-/
-     */
-    NFA *nfa = new_nfa();
-    State start = increase_state_counts();
-    NFAFragment *left = ast2nfa_fragment(ast->data.AstOr.r1);
-    NFAFragment *right = ast2nfa_fragment(ast->data.AstOr.r2);
-    State accept = increase_state_counts();
-    add_epsilon(nfa, start, left->start);
-    add_epsilon(nfa, start, right->start);
-    add_epsilon(nfa, left->accept, accept);
-    add_epsilon(nfa, right->accept, accept);
-    move_edges(nfa, left->nfa);
-    move_edges(nfa, right->nfa);
-    free(left->nfa);
-    free(right->nfa);
-    NFAFragment *result = new_nfa_fragment(nfa, start, accept);
-    free(left);
-    free(right);
-    return result;
-  }
-
-  case RepeatNode: {
-    /*
-     *               .-<-ε-<-.
-     *              /         \
-     * START --ε--> S₀ --r--> S₁ --ε--> END
-     *     \                            /
-     *      .---------->-ε->-----------.
-     */
-    NFA *nfa = new_nfa();
-    State start = increase_state_counts();
-    NFAFragment *body = ast2nfa_fragment(ast->data.AstRepeat.r);
-    State accept = increase_state_counts();
-    move_edges(nfa, body->nfa);
-    add_epsilon(nfa, start, body->start);
-    add_epsilon(nfa, start, accept);
-    add_epsilon(nfa, body->accept, body->start);
-    add_epsilon(nfa, body->accept, accept);
-    free(body->nfa);
-    NFAFragment *result = new_nfa_fragment(nfa, start, accept);
-    free(body);
-    return result;
+pub const REENTRANT: &str = r#"
+/* make a scanner with the extra data, return 0 on success */
+int yylex_init_extra(YY_EXTRA_TYPE extra, yyscan_t *scanner) {
+  if (scanner == NULL) {
+    errno = EINVAL;
+    return 1;
   }
-
-  case SurroundNode: {
-    /* START --r--> END */
-    return ast2nfa_fragment(ast->data.AstSurround.r);
+  struct yyguts_t *yyg = malloc(sizeof(struct yyguts_t));
+  if (yyg == NULL) {
+    errno = ENOMEM;
+    return 1;
   }
+  *yyg = yy_initial_guts;
+  yyextra = extra;
+  *scanner = yyg;
+  return 0;
+}
 
-  default:
-    exit(1);
-  }
+int yylex_init(yyscan_t *scanner) { return yylex_init_extra(NULL, scanner); }
+
+/* free the scanner with the buffers on its stack */
+int yylex_destroy(yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  while (YY_CURRENT_BUFFER)
+    yypop_buffer_state(yyscanner);
+  free(g_buffer_stack);
+#ifdef YY_USES_STATE_BUF
+  free(g_state_buf);
+#endif
+  free(yyg);
+  return 0;
 }
 
-NFA *ast2nfa(Ast *ast) {
-  NFAFragment *fragment = ast2nfa_fragment(ast);
-  NFA *nfa = fragment->nfa;
-  free(fragment);
-  free_ast(ast);
+YY_EXTRA_TYPE yyget_extra(yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  return yyextra;
+}
 
-  nfa->states_count = g_state_counts;
+void yyset_extra(YY_EXTRA_TYPE extra, yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  yyextra = extra;
+}
 
-  States *target_states = new_states();
-  push_state(target_states, g_state_counts - 1);
-  nfa->target_states = target_states;
-  return nfa;
+FILE *yyget_in(yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  return yyin;
 }
 
-NFA *build(char *pattern) {
-  Lexer *lexer = new_lexer(pattern);
-  Parser *parser = new_parser(lexer);
-  Ast *ast = parse(parser);
-  NFA *nfa = ast2nfa(ast);
-  if (lexer->current_token != NULL) {
-    free(lexer->current_token);
-  }
-  free(lexer);
-  free(parser);
-  return nfa;
+void yyset_in(FILE *file, yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  yyin = file;
 }
 
-NFA *build_many(char **patterns, size_t len) {
-  g_state_counts = 0;
-  NFA *nfa = new_nfa();
-  nfa->target_states = new_states();
-  State start = increase_state_counts();
-
-  for (size_t i = 0; i < len; ++i) {
-    State sub_start = get_state_counts();
-    NFA *sub_nfa = build(patterns[i]);
-    move_edges(nfa, sub_nfa);
-    add_epsilon(nfa, start, sub_start);
-    push_state(nfa->target_states, sub_nfa->target_states->states[0]);
-    free(sub_nfa);
-  }
+FILE *yyget_out(yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  return yyout;
+}
 
-  return nfa;
+void yyset_out(FILE *file, yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  yyout = file;
 }
 
-/*
- * ============================================================================
- * match.c - Functions to match string with patterns
- * ============================================================================
- */
+char *yyget_text(yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  return yytext;
+}
 
-typedef unsigned long IdxType;
+IdxType yyget_leng(yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  return yyleng;
+}
 
-/* if the input string fully matches the pattern */
-bool match_full(NFA *nfa, char *input) {
-  States *s = new_states();
-  push_state(s, 0);
-  s = epsilon_closure(nfa, s);
+#ifdef YY_USES_LINENO
+int yyget_lineno(yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  return yylineno;
+}
 
-  char *next_char = input;
-  while (*next_char != '\0') {
-    s = epsilon_closure(nfa, move(nfa, s, *next_char));
-    ++next_char;
-  }
-  bool result = get_shared_states(s, nfa->target_states);
-  free(s);
-  return result;
+void yyset_lineno(int line, yyscan_t yyscanner) {
+  YY_DECL_GUTS_VAR();
+  yylineno = line;
 }
+#endif
+"#;
+
+pub const YYLEX_START: &str = r#"
+#ifndef YY_DECL
+#define YY_DECL int yylex(YY_ONLY_ARG)
+#endif
 
 /*
- * find the first longest match, and copy it to (char *)text, return its length
+ * scan the next token, actions returning a value return it from here, and
+ * the next call continues after the token. at the end of input the <<EOF>>
+ * rule of the start condition runs, by default 0 is returned and the next
+ * call reads yyin again
  */
-IdxType match(NFA *nfa, char *input, char *text) {
-  States *s = new_states();
-  push_state(s, 0);
-  s = epsilon_closure(nfa, s);
+YY_DECL {
+  YY_DECL_GUTS_VAR();
+  /* the actions may not use the values of a bison parser */
+#ifdef YY_BISON_BRIDGE
+  (void)yylval_param;
+#endif
+#ifdef YY_BISON_LOCATIONS
+  (void)yylloc_param;
+#endif
+  if (yyin == NULL)
+    yyin = stdin;
+  if (yyout == NULL)
+    yyout = stdout;
 
-  IdxType len = 0;
-  IdxType last_match = 0;
-  char *next_char = input;
-  while (*next_char != '\0') {
-    s = epsilon_closure(nfa, move(nfa, s, *next_char));
-
-    /*
-     * if nothing matches, return to the starting state.
-     * however, if there is any match, stop matching and return it as the
-     * longest match
-     */
-    if (states_is_empty(s)) {
-      if (last_match > 0)
-        break;
-      else {
-        push_state(s, 0);
-        s = epsilon_closure(nfa, s);
-      }
+  for (;;) {
+    int pattern_index;
+    if (YY_CURRENT_BUFFER == NULL) {
+      yyrestart(yyin YY_CALL_LAST_ARG);
+      g_yyin_buffer = YY_CURRENT_BUFFER;
+    }
+    yy_restore_yytext(YY_CALL_ONLY_ARG);
+    g_more_len = g_more ? yyleng : 0;
+    g_more = 0;
+    if (yy_fill_buffer(0 YY_CALL_LAST_ARG)) {
+      pattern_index = yy_match(YY_CALL_ONLY_ARG);
+#ifdef YY_USES_REJECT
+      /* REJECT comes back here with the next match */
+    yy_find_action:;
+#endif
+#ifdef YY_USES_LINENO
+      yy_update_lineno(pattern_index YY_CALL_LAST_ARG);
+#endif
+    } else if (!yywrap(YY_CALL_ONLY_ARG)) {
+      /* go on with the yyin set by yywrap */
+      yyrestart(yyin YY_CALL_LAST_ARG);
+      continue;
     } else {
-      text[(len)++] = *next_char;
+      pattern_index = YY_STATE_EOF(YY_START);
     }
 
-    /* if any target state is reached, mark matching */
-    if (get_shared_states(s, nfa->target_states))
-      last_match = len;
+    switch (pattern_index) {
+"#;
 
-    ++next_char;
+pub const YYLEX_END: &str = r#"    default:
+      /* the end of input without an <<EOF>> rule */
+      yyterminate();
+    }
   }
-  len = last_match;
-  text[len] = '\0';
-  free(s);
-  return len;
-}
+}"#;
 
-/*
- * similar to `match`, but copy to yytext, assign its length to yyleng,
- * and return the index of the pattern matched
- */
-int yy_match(NFA *nfa) {
-  States *s = new_states();
-  push_state(s, 0);
-  s = epsilon_closure(nfa, s);
-
-  yyleng = 0;
-  IdxType last_match = 0;
-  State last_shared_state = 0;
-  while (g_buffer_ptr < g_buffer + g_buflen) {
-    s = epsilon_closure(nfa, move(nfa, s, *g_buffer_ptr));
-
-    if (states_is_empty(s)) {
-      if (last_match > 0)
-        break;
-      else {
-        push_state(s, 0);
-        s = epsilon_closure(nfa, s);
-      }
-    } else {
-      yytext[(yyleng)++] = *g_buffer_ptr;
-    }
+pub const MAIN: &str = r#"
 
-    /* if any target state is reached, mark matching */
-    if ((last_shared_state = get_shared_states(nfa->target_states, s))) {
-      last_match = yyleng;
-    }
+/* scan the standard input to the end */
+int main(void) {
+  while (yylex() != 0)
+    ;
+  return 0;
+}"#;
 
-    ++g_buffer_ptr;
-  }
-  yyleng = last_match;
-  yytext[yyleng] = '\0';
+pub const REENTRANT_MAIN: &str = r#"
 
-  for (size_t i = 0; i < nfa->target_states->len; ++i) {
-    if (nfa->target_states->states[i] == last_shared_state) {
-      free(s);
-      return i;
-    }
-  }
+/* scan the standard input to the end */
+int main(void) {
+  yyscan_t scanner;
+  if (yylex_init(&scanner) != 0)
+    return 1;
+  while (yylex(scanner) != 0)
+    ;
+  yylex_destroy(scanner);
+  return 0;
+}"#;
 
-  /* unreachable */
-  exit(EXIT_FAILURE);
-}
+pub const HEADER_TYPES: &str = r#"#include <stdio.h>
+
+/* the types are shared by the headers of every scanner */
+#ifndef YY_TYPEDEF_YY_BUFFER_STATE
+#define YY_TYPEDEF_YY_BUFFER_STATE
+typedef unsigned long IdxType;
+
+/* an input source being scanned, a file or bytes in memory */
+typedef struct yy_buffer_state *YY_BUFFER_STATE;
+#endif
 
 "#;
 
-pub const YYLEX: &str = r#"
-int yylex() {
-  if (yyin == NULL)
-    yyin = stdin;
-  if (yyout == NULL)
-    yyout = stdout;
+pub const HEADER_BUFFER: &str = r#"void yyrestart(FILE *file YY_LAST_ARG);
+YY_BUFFER_STATE yy_create_buffer(FILE *file, int size YY_LAST_ARG);
+void yy_switch_to_buffer(YY_BUFFER_STATE buffer YY_LAST_ARG);
+void yy_delete_buffer(YY_BUFFER_STATE buffer YY_LAST_ARG);
+void yypush_buffer_state(YY_BUFFER_STATE buffer YY_LAST_ARG);
+void yypop_buffer_state(YY_ONLY_ARG);
+YY_BUFFER_STATE yy_scan_buffer(char *base, IdxType size YY_LAST_ARG);
+YY_BUFFER_STATE yy_scan_bytes(const char *bytes, IdxType len YY_LAST_ARG);
+YY_BUFFER_STATE yy_scan_string(const char *str YY_LAST_ARG);
 
-  yy_read_buffer();
-  g_buffer_ptr = g_buffer;
-  NFA *nfa = build_many(g_patterns, g_pattern_count);
-  while (g_buffer_ptr < g_buffer + g_buflen) {
-    int pattern_idx = yy_match(nfa);
-    action(pattern_idx);
-  }
-  return 0;
-}"#;
+"#;
+
+// the macros the declarations of a header are written with, undefined at its
+// end so that the headers of several scanners can be included together
+pub const HEADER_MACROS: &[&str] = &[
+    "YY_ONLY_ARG",
+    "YY_LAST_ARG",
+    "YY_CALL_ONLY_ARG",
+    "YY_CALL_LAST_ARG",
+    "YY_DECL_GUTS_VAR",
+];
+
+pub const HEADER_REENTRANT: &str = r#"int yylex_init_extra(YY_EXTRA_TYPE extra, yyscan_t *scanner);
+int yylex_init(yyscan_t *scanner);
+int yylex_destroy(yyscan_t yyscanner);
+YY_EXTRA_TYPE yyget_extra(yyscan_t yyscanner);
+void yyset_extra(YY_EXTRA_TYPE extra, yyscan_t yyscanner);
+FILE *yyget_in(yyscan_t yyscanner);
+void yyset_in(FILE *file, yyscan_t yyscanner);
+FILE *yyget_out(yyscan_t yyscanner);
+void yyset_out(FILE *file, yyscan_t yyscanner);
+char *yyget_text(yyscan_t yyscanner);
+IdxType yyget_leng(yyscan_t yyscanner);
+"#;
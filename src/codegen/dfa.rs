@@ -0,0 +1,365 @@
+// convert the NFA to a Deterministic Finite Automaton (subset construction)
+
+use super::nfa::{Nfa, State};
+use std::collections::HashMap;
+
+/// the state without any way out, every missing transition goes here
+pub const DEAD: usize = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DfaState {
+    /// next state for every byte
+    pub next: Vec<usize>,
+    /// index of the first rule accepted in this state
+    pub accept: Option<usize>,
+    /// index of the first rule accepted before a newline or the end of input,
+    /// including `$` rules
+    pub eol_accept: Option<usize>,
+    /// indices of every rule accepted in this state in order, only kept for
+    /// REJECT
+    pub accept_list: Vec<usize>,
+    /// the same before a newline or the end of input
+    pub eol_accept_list: Vec<usize>,
+    /// indices of the rules with variable trailing context whose heads end here
+    pub head_ends: Vec<usize>,
+}
+
+impl DfaState {
+    fn dead() -> Self {
+        DfaState {
+            next: vec![DEAD; 256],
+            accept: None,
+            eol_accept: None,
+            accept_list: Vec::new(),
+            eol_accept_list: Vec::new(),
+            head_ends: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Dfa {
+    pub states: Vec<DfaState>,
+    /// the start states in the middle of a line and at the beginning of a line,
+    /// for every start condition in turn
+    pub starts: Vec<usize>,
+}
+
+impl Dfa {
+    /// the lists of every rule accepted are only kept when asked, they keep
+    /// more states apart
+    pub fn from_nfa(nfa: &Nfa, accept_lists: bool) -> Self {
+        let mut states = vec![DfaState::dead()];
+        // the NFA states of every DFA state, and the way back
+        let mut subsets: Vec<Vec<State>> = vec![Vec::new()];
+        let mut indices: HashMap<Vec<State>, usize> = HashMap::new();
+
+        let mut starts = Vec::new();
+        for &start in &nfa.starts {
+            let mut start = vec![start];
+            nfa.epsilon_closure(&mut start);
+            starts.push(*indices.entry(start).or_insert_with_key(|start| {
+                subsets.push(start.clone());
+                subsets.len() - 1
+            }));
+        }
+
+        let mut current = 1;
+        while current < subsets.len() {
+            let mut targets: Vec<Vec<State>> = vec![Vec::new(); 256];
+            for &state in &subsets[current] {
+                for (label, to) in &nfa.states[state].edges {
+                    for byte in label.iter() {
+                        targets[byte as usize].push(*to);
+                    }
+                }
+            }
+
+            let mut next = vec![DEAD; 256];
+            for (byte, mut target) in targets.into_iter().enumerate() {
+                if target.is_empty() {
+                    continue;
+                }
+                nfa.epsilon_closure(&mut target);
+                next[byte] = *indices.entry(target).or_insert_with_key(|target| {
+                    subsets.push(target.clone());
+                    subsets.len() - 1
+                });
+            }
+
+            // the rule listed first wins when several rules match
+            let accepts = subsets[current]
+                .iter()
+                .map(|&state| &nfa.states[state])
+                .filter(|state| state.accept.is_some());
+            let mut accept_list = accepts
+                .clone()
+                .filter(|state| !state.eol)
+                .filter_map(|state| state.accept)
+                .collect::<Vec<_>>();
+            accept_list.sort_unstable();
+            accept_list.dedup();
+            let mut eol_accept_list = accepts.filter_map(|state| state.accept).collect::<Vec<_>>();
+            eol_accept_list.sort_unstable();
+            eol_accept_list.dedup();
+            let accept = accept_list.first().copied();
+            let eol_accept = eol_accept_list.first().copied();
+            if !accept_lists {
+                accept_list.clear();
+                eol_accept_list.clear();
+            }
+            let mut head_ends = subsets[current]
+                .iter()
+                .filter_map(|&state| nfa.states[state].head_end)
+                .collect::<Vec<_>>();
+            head_ends.sort_unstable();
+            states.push(DfaState {
+                next,
+                accept,
+                eol_accept,
+                accept_list,
+                eol_accept_list,
+                head_ends,
+            });
+            current += 1;
+        }
+
+        Dfa { states, starts }
+    }
+
+    /// number bytes by their columns in the transition table, bytes in the
+    /// same equivalence class always lead to the same state
+    pub fn equivalence_classes(&self) -> Vec<usize> {
+        let mut classes = vec![0; 256];
+        let mut indices: HashMap<Vec<usize>, usize> = HashMap::new();
+        for (byte, class) in classes.iter_mut().enumerate() {
+            let column = self.states.iter().map(|state| state.next[byte]).collect();
+            let count = indices.len();
+            *class = *indices.entry(column).or_insert(count);
+        }
+        classes
+    }
+
+    /// merge equivalent states with Hopcroft's partition refinement
+    pub fn minimize(&self) -> Dfa {
+        let classes = self.equivalence_classes();
+        // one byte standing for every equivalence class
+        let mut symbols = Vec::new();
+        for (byte, &class) in classes.iter().enumerate() {
+            if class == symbols.len() {
+                symbols.push(byte);
+            }
+        }
+
+        // the states moving to every state on every symbol
+        let mut incoming = vec![vec![Vec::new(); self.states.len()]; symbols.len()];
+        for (from, state) in self.states.iter().enumerate() {
+            for (symbol, &byte) in symbols.iter().enumerate() {
+                incoming[symbol][state.next[byte]].push(from);
+            }
+        }
+
+        // start with the dead state alone, and other states grouped by the rule accepted
+        let mut blocks: Vec<Vec<usize>> = Vec::new();
+        let mut block_of = vec![0; self.states.len()];
+        let mut initial = HashMap::new();
+        for (index, state) in self.states.iter().enumerate() {
+            let key = (
+                index == DEAD,
+                state.accept,
+                state.eol_accept,
+                state.accept_list.clone(),
+                state.eol_accept_list.clone(),
+                state.head_ends.clone(),
+            );
+            let block = *initial.entry(key).or_insert_with(|| {
+                blocks.push(Vec::new());
+                blocks.len() - 1
+            });
+            blocks[block].push(index);
+            block_of[index] = block;
+        }
+
+        let mut pending: Vec<usize> = (0..blocks.len()).collect();
+        let mut is_pending = vec![true; blocks.len()];
+        while let Some(splitter) = pending.pop() {
+            is_pending[splitter] = false;
+            let members = blocks[splitter].clone();
+            for incoming in &incoming {
+                // states entering the splitter, grouped by their blocks
+                let mut entering: HashMap<usize, Vec<usize>> = HashMap::new();
+                for &to in &members {
+                    for &from in &incoming[to] {
+                        entering.entry(block_of[from]).or_default().push(from);
+                    }
+                }
+
+                for (block, inside) in entering {
+                    if inside.len() == blocks[block].len() {
+                        continue;
+                    }
+                    let new_block = blocks.len();
+                    for &state in &inside {
+                        block_of[state] = new_block;
+                    }
+                    blocks[block].retain(|&state| block_of[state] == block);
+                    let smaller = if is_pending[block] || inside.len() <= blocks[block].len() {
+                        new_block
+                    } else {
+                        block
+                    };
+                    blocks.push(inside);
+                    is_pending.push(false);
+                    if !is_pending[smaller] {
+                        is_pending[smaller] = true;
+                        pending.push(smaller);
+                    }
+                }
+            }
+        }
+
+        // number the blocks in the order of their first states, so the dead
+        // state and the start states keep their numbers
+        let mut numbers = vec![None; blocks.len()];
+        let mut representatives = Vec::new();
+        for state in 0..self.states.len() {
+            if numbers[block_of[state]].is_none() {
+                numbers[block_of[state]] = Some(representatives.len());
+                representatives.push(state);
+            }
+        }
+        let states = representatives
+            .into_iter()
+            .map(|state| DfaState {
+                next: self.states[state]
+                    .next
+                    .iter()
+                    .map(|&next| numbers[block_of[next]].unwrap())
+                    .collect(),
+                accept: self.states[state].accept,
+                eol_accept: self.states[state].eol_accept,
+                accept_list: self.states[state].accept_list.clone(),
+                eol_accept_list: self.states[state].eol_accept_list.clone(),
+                head_ends: self.states[state].head_ends.clone(),
+            })
+            .collect();
+
+        Dfa {
+            states,
+            starts: self
+                .starts
+                .iter()
+                .map(|&start| numbers[block_of[start]].unwrap())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codegen::regex::parse;
+
+    impl Dfa {
+        /// the rule and length of the longest match at the beginning of input
+        fn longest_match(&self, input: &[u8]) -> Option<(usize, usize)> {
+            self.longest_match_at(input, false)
+        }
+
+        fn longest_match_at(&self, input: &[u8], at_bol: bool) -> Option<(usize, usize)> {
+            let mut state = self.starts[at_bol as usize];
+            let mut last_match = None;
+            for (len, &byte) in input.iter().enumerate() {
+                state = self.states[state].next[byte as usize];
+                if state == DEAD {
+                    break;
+                }
+                let accept = match input.get(len + 1) {
+                    None | Some(b'\n') => self.states[state].eol_accept,
+                    _ => self.states[state].accept,
+                };
+                if let Some(rule) = accept {
+                    last_match = Some((rule, len + 1));
+                }
+            }
+            last_match
+        }
+    }
+
+    #[test]
+    fn subset_construction() {
+        let rules = ["lorem", "[0-9]+", "fo*", "[a-z]+", ".|\\n"].map(parse);
+        let dfa = Dfa::from_nfa(
+            &Nfa::from_rules(&rules, &[(0..rules.len()).collect()]),
+            false,
+        );
+        assert_eq!(dfa.longest_match(b"lorem ipsum"), Some((0, 5)));
+        assert_eq!(dfa.longest_match(b"loremipsum"), Some((3, 10)));
+        assert_eq!(dfa.longest_match(b"114514foo"), Some((1, 6)));
+        assert_eq!(dfa.longest_match(b"foooo\n"), Some((2, 5)));
+        assert_eq!(dfa.longest_match(b"foobar"), Some((3, 6)));
+        assert_eq!(dfa.longest_match(b"\n"), Some((4, 1)));
+        assert_eq!(dfa.longest_match(b""), None);
+    }
+
+    #[test]
+    fn repetition() {
+        let rules = ["-?[0-9]{2,3}", "a{2}b{0,}c?", "[0-9a-c]", ".|\\n"].map(parse);
+        let dfa = Dfa::from_nfa(
+            &Nfa::from_rules(&rules, &[(0..rules.len()).collect()]),
+            false,
+        );
+        assert_eq!(dfa.longest_match(b"-12345"), Some((0, 4)));
+        assert_eq!(dfa.longest_match(b"12"), Some((0, 2)));
+        assert_eq!(dfa.longest_match(b"-1"), Some((3, 1)));
+        assert_eq!(dfa.longest_match(b"aabbbc"), Some((1, 6)));
+        assert_eq!(dfa.longest_match(b"aac"), Some((1, 3)));
+        assert_eq!(dfa.longest_match(b"ab"), Some((2, 1)));
+    }
+
+    #[test]
+    fn anchors() {
+        let rules = ["^#include", "end$", "[a-z#]+", ".|\\n"].map(parse);
+        let dfa = Dfa::from_nfa(
+            &Nfa::from_rules(&rules, &[(0..rules.len()).collect()]),
+            false,
+        )
+        .minimize();
+        assert_ne!(dfa.starts[0], dfa.starts[1]);
+        assert_eq!(dfa.longest_match_at(b"#include", true), Some((0, 8)));
+        assert_eq!(dfa.longest_match_at(b"#include", false), Some((2, 8)));
+        assert_eq!(dfa.longest_match_at(b"#includes", true), Some((2, 9)));
+        assert_eq!(dfa.longest_match(b"end\n"), Some((1, 3)));
+        assert_eq!(dfa.longest_match(b"end"), Some((1, 3)));
+        assert_eq!(dfa.longest_match(b"end "), Some((2, 3)));
+        assert_eq!(dfa.longest_match(b"ends"), Some((2, 4)));
+    }
+
+    #[test]
+    fn minimize() {
+        let rules = ["[0-9]+", "pattern3", ".|\\n"].map(parse);
+        let dfa = Dfa::from_nfa(
+            &Nfa::from_rules(&rules, &[(0..rules.len()).collect()]),
+            false,
+        );
+        let minimized = dfa.minimize();
+        // the start states, the first and other digits, and newline and other
+        // characters are merged
+        assert_eq!(dfa.states.len(), 15);
+        assert_eq!(minimized.states.len(), 12);
+        assert_eq!(minimized.starts, vec![1, 1]);
+        for input in ["114514", "pattern3", "patterns", "p", "\n", ""] {
+            assert_eq!(
+                minimized.longest_match(input.as_bytes()),
+                dfa.longest_match(input.as_bytes())
+            );
+        }
+
+        let classes = minimized.equivalence_classes();
+        // other digits, "3", each letter of "patern", and everything else
+        assert_eq!(classes.iter().max(), Some(&8));
+        assert_eq!(classes[b'0' as usize], classes[b'9' as usize]);
+        assert_eq!(classes[b'\n' as usize], classes[b'x' as usize]);
+        assert_ne!(classes[b'3' as usize], classes[b'4' as usize]);
+    }
+}
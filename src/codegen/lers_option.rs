@@ -1,4 +1,258 @@
 #[derive(Debug, PartialEq)]
 pub enum LersOption {
-    Noyywrap,
+    /// call yywrap at the end of input, `noyywrap` acts as if it returned 1
+    Yywrap(bool),
+    /// full transition tables indexed by byte, fast but large (-Cf)
+    Full,
+    /// compressed tables indexed by equivalence class, small but slower (-Ce)
+    Ecs,
+    /// yytext is a copy of the token in an array of YYLMAX bytes (`%array`)
+    Array,
+    /// yytext points into the input buffer, the default (`%pointer`)
+    Pointer,
+    /// count the lines of the input in yylineno
+    Yylineno(bool),
+    /// keep the lines, columns and byte offsets of every token in yylocation,
+    /// implies `Yylineno`
+    Locations(bool),
+    /// `nounput` leaves unput out of the scanner
+    Unput(bool),
+    /// `noinput` leaves input out of the scanner
+    Input(bool),
+    /// `nodefault` makes unmatched input a fatal error instead of echoing it
+    Default(bool),
+    /// a main function calling yylex until the end of input, implies `noyywrap`
+    Main(bool),
+    /// start the exported symbols with this instead of yy, so that scanners
+    /// with different prefixes link into one program (-P)
+    Prefix(String),
+    /// keep the state in a `yyscan_t` made by yylex_init instead of globals
+    Reentrant(bool),
+    /// the type of yyextra in a reentrant scanner, `void *` by default
+    ExtraType(String),
+    /// `yylex(YYSTYPE *)` for pure bison parsers, yylval is the argument
+    BisonBridge(bool),
+    /// `yylex(YYSTYPE *, YYLTYPE *)` with yylloc as well, implies `BisonBridge`
+    BisonLocations(bool),
+    /// `nointeractive` (or `batch`) reads ahead after a token which cannot go
+    /// on, instead of stopping there so that it is matched as soon as it is
+    /// typed (-B)
+    Interactive(bool),
+    /// read every input a line at a time as from a terminal, without asking
+    /// isatty
+    AlwaysInteractive(bool),
+    /// read every input in blocks, without asking isatty
+    NeverInteractive(bool),
+    /// `nounistd` leaves out unistd.h and isatty, for systems without them
+    Unistd(bool),
+    /// write the scanner to this file instead of lers.yy.c, `-` is the standard
+    /// output (-o)
+    Outfile(String),
+    /// write the scanner to the standard output (-t)
+    Stdout(bool),
+    /// also write the declarations of the scanner to this C header, for the
+    /// other files of a program (--header-file)
+    HeaderFile(String),
+    /// a bison generated header included before the rules, for the token numbers
+    /// and YYSTYPE (--bison-header)
+    BisonHeader(String),
+}
+
+// flex options which change nothing in the scanner lers generates, either
+// because lers always behaves that way or because they only tune flex itself
+const NO_EFFECT: &[&str] = &[
+    "7bit",
+    "8bit",
+    "align",
+    "backup",
+    "perf-report",
+    "verbose",
+    "warn",
+    "line",
+    "read",
+    "meta-ecs",
+    "stdinit",
+    "yymore",
+    "reject",
+    "tables-verify",
+    "yy_scan_buffer",
+    "yy_scan_bytes",
+    "yy_scan_string",
+    "yy_push_state",
+    "yy_pop_state",
+    "yy_top_state",
+    "yyalloc",
+    "yyrealloc",
+    "yyfree",
+    "yyget_extra",
+    "yyset_extra",
+    "yyget_leng",
+    "yyget_text",
+    "yyget_lineno",
+    "yyset_lineno",
+    "yyget_column",
+    "yyset_column",
+    "yyget_in",
+    "yyset_in",
+    "yyget_out",
+    "yyset_out",
+    "yyget_lval",
+    "yyset_lval",
+    "yyget_lloc",
+    "yyset_lloc",
+    "yyget_debug",
+    "yyset_debug",
+];
+
+// flex options whose behavior lers does not have
+const UNSUPPORTED: &[&str] = &[
+    "c++",
+    "yyclass",
+    "lex-compat",
+    "posix-compat",
+    "debug",
+    "stack",
+    "tables-file",
+];
+
+impl LersOption {
+    /// the option of `%option name` or `%option name=value`, `noname` turns a
+    /// flag off; `None` for the flex options which change nothing in lers
+    pub fn parse(name: &str, value: Option<&str>) -> Option<LersOption> {
+        // no flex option starts with no
+        let (flag, on) = match name.strip_prefix("no") {
+            Some(flag) => (flag, false),
+            None => (name, true),
+        };
+        // turning them off is what lers does anyway
+        if UNSUPPORTED.contains(&flag) && on {
+            panic!("Unsupported option: {}", name);
+        } else if UNSUPPORTED.contains(&flag) {
+            return None;
+        }
+        let takes_value = matches!(flag, "prefix" | "extra-type" | "header-file" | "outfile");
+        match (takes_value, on, value) {
+            (true, false, _) => panic!("Unknown option: {}", name),
+            (true, true, None) => panic!("Option {} takes a value", name),
+            (false, _, Some(_)) => panic!("Option {} takes no value", name),
+            _ => {}
+        }
+        let option = match flag {
+            "yywrap" => LersOption::Yywrap(on),
+            "full" | "fast" if on => LersOption::Full,
+            "full" | "fast" => LersOption::Ecs,
+            "ecs" if on => LersOption::Ecs,
+            "ecs" => LersOption::Full,
+            "array" if on => LersOption::Array,
+            "array" => LersOption::Pointer,
+            "pointer" if on => LersOption::Pointer,
+            "pointer" => LersOption::Array,
+            "yylineno" => LersOption::Yylineno(on),
+            "locations" => LersOption::Locations(on),
+            "unput" => LersOption::Unput(on),
+            "input" => LersOption::Input(on),
+            "default" => LersOption::Default(on),
+            "main" => LersOption::Main(on),
+            "reentrant" => LersOption::Reentrant(on),
+            "bison-bridge" => LersOption::BisonBridge(on),
+            "bison-locations" => LersOption::BisonLocations(on),
+            "prefix" => LersOption::Prefix(value.unwrap().to_string()),
+            "extra-type" => LersOption::ExtraType(value.unwrap().to_string()),
+            "header-file" => LersOption::HeaderFile(value.unwrap().to_string()),
+            "outfile" => LersOption::Outfile(value.unwrap().to_string()),
+            "stdout" => LersOption::Stdout(on),
+            "interactive" => LersOption::Interactive(on),
+            "batch" => LersOption::Interactive(!on),
+            "always-interactive" => LersOption::AlwaysInteractive(on),
+            "never-interactive" => LersOption::NeverInteractive(on),
+            "unistd" => LersOption::Unistd(on),
+            // lers matches bytes, so both are always the case
+            "caseful" | "case-sensitive" if on => return None,
+            "caseless" | "case-insensitive" if !on => return None,
+            "caseful" | "case-sensitive" | "caseless" | "case-insensitive" => {
+                panic!("Unsupported option: {}", name)
+            }
+            _ if NO_EFFECT.contains(&flag) => return None,
+            _ => panic!("Unknown option: {}", name),
+        };
+        Some(option)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            LersOption::parse("yywrap", None),
+            Some(LersOption::Yywrap(true))
+        );
+        assert_eq!(
+            LersOption::parse("noyywrap", None),
+            Some(LersOption::Yywrap(false))
+        );
+        assert_eq!(
+            LersOption::parse("nounput", None),
+            Some(LersOption::Unput(false))
+        );
+        assert_eq!(LersOption::parse("noecs", None), Some(LersOption::Full));
+        assert_eq!(
+            LersOption::parse("noarray", None),
+            Some(LersOption::Pointer)
+        );
+        assert_eq!(
+            LersOption::parse("prefix", Some("calc")),
+            Some(LersOption::Prefix("calc".to_string()))
+        );
+        assert_eq!(
+            LersOption::parse("nostdout", None),
+            Some(LersOption::Stdout(false))
+        );
+        assert_eq!(
+            LersOption::parse("batch", None),
+            Some(LersOption::Interactive(false))
+        );
+        assert_eq!(
+            LersOption::parse("always-interactive", None),
+            Some(LersOption::AlwaysInteractive(true))
+        );
+        assert_eq!(
+            LersOption::parse("nonever-interactive", None),
+            Some(LersOption::NeverInteractive(false))
+        );
+        assert_eq!(
+            LersOption::parse("nounistd", None),
+            Some(LersOption::Unistd(false))
+        );
+        assert_eq!(LersOption::parse("8bit", None), None);
+        assert_eq!(LersOption::parse("noyy_scan_string", None), None);
+        assert_eq!(LersOption::parse("nocaseless", None), None);
+        assert_eq!(LersOption::parse("nostack", None), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown option: yywarp")]
+    fn unknown() {
+        LersOption::parse("yywarp", None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported option: c++")]
+    fn unsupported() {
+        LersOption::parse("c++", None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Option prefix takes a value")]
+    fn missing_value() {
+        LersOption::parse("prefix", None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Option yylineno takes no value")]
+    fn unexpected_value() {
+        LersOption::parse("yylineno", Some("1"));
+    }
 }
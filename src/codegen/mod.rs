@@ -1,27 +1,61 @@
 mod code;
+mod dfa;
 mod lers_option;
+mod nfa;
+mod regex;
+mod table;
+mod variable;
+
+pub use lers_option::LersOption;
 
 use crate::ast::*;
 use code::*;
+use dfa::{DEAD, Dfa};
+use nfa::Nfa;
+use regex::Pattern;
+use table::CompressedTable;
+use variable::{gen_globals, gen_guts};
+
+// the pattern of the rules run at the end of input
+const EOF_PATTERN: &str = "<<EOF>>";
+
+// the value of the last option turning a flag on or off
+macro_rules! last_flag {
+    ($codegen:expr, $flag:ident) => {
+        $codegen.last(|option| match option {
+            LersOption::$flag(on) => Some(*on),
+            _ => None,
+        })
+    };
+}
 
 #[derive(Default, Debug, PartialEq)]
 struct RuleTable<'a> {
     pair_count: usize,
     patterns: Vec<&'a str>,
     actions: Vec<&'a str>,
+    start_conditions: Vec<Vec<&'a str>>,
+    eof_actions: Vec<&'a str>,
+    eof_start_conditions: Vec<Vec<&'a str>>,
 }
 
 impl<'a> RuleTable<'a> {
-    pub fn append_pair(&mut self, pattern: &'a str, action: &'a str) {
+    pub fn append_pair(&mut self, pattern: &'a str, action: &'a str, start_conditions: &[&'a str]) {
+        if pattern == EOF_PATTERN {
+            self.eof_actions.push(action);
+            self.eof_start_conditions.push(start_conditions.to_vec());
+            return;
+        }
         self.pair_count += 1;
         self.patterns.push(pattern);
         self.actions.push(action);
+        self.start_conditions.push(start_conditions.to_vec());
     }
 }
 
 pub struct CodeGen<'a> {
     ast: Root<'a>,
-    options: Vec<lers_option::LersOption>,
+    options: Vec<LersOption>,
     rule_table: RuleTable<'a>,
 }
 
@@ -34,22 +68,37 @@ impl<'a> CodeGen<'a> {
         }
     }
 
+    /// add an option given outside the lex file, it overrides the `%option`s
+    pub fn add_option(&mut self, option: LersOption) {
+        self.options.push(option);
+    }
+
     pub fn generate(&mut self) -> String {
         let mut code = String::new();
 
-        // Generate code from definition node
-        if let Some(def_node) = &self.ast.definition_node {
-            if let Some(code_node) = &def_node.code {
-                code.push_str(&format!("/*** Definition Code ***/\n{}\n", code_node.value));
-            }
+        if self.ast.definition_node.is_some() {
             self.apply_options();
         }
+        // the renames come first so the definition code sees them too
+        code.push_str(&self.gen_prefix());
+        // and they stay when the definition code includes the header
+        if self.header_file().is_some() {
+            code.push_str("#define YY_HEADER_NO_UNDEFS\n\n");
+        }
+
+        // Generate code from definition node
+        if let Some(def_node) = &self.ast.definition_node
+            && let Some(code_node) = &def_node.code
+        {
+            code.push_str(&format!("/*** Definition Code ***/\n{}\n", code_node.value));
+        }
 
         // Visit rule node and generate rule table
         if let Some(rule_node) = &self.ast.rule_node {
             if let Some(rules) = rule_node.rules.as_ref() {
                 for pair in rules {
-                    self.rule_table.append_pair(pair.pattern, pair.action);
+                    self.rule_table
+                        .append_pair(pair.pattern, pair.action, &pair.start_conditions);
                 }
             }
             code.push_str(&format!("/*** Rule Code ***/\n{}\n", &self.gen_rule_code()));
@@ -63,70 +112,900 @@ impl<'a> CodeGen<'a> {
         code
     }
 
-    fn apply_options(&mut self) {
-        if let Some(options) = &self.ast.definition_node.as_ref().unwrap().options {
-            for option in options {
-                #[allow(clippy::single_match)]
-                match option.value {
-                    "noyywrap" => self.options.push(lers_option::LersOption::Noyywrap),
-                    _ => {}
-                }
+    /// the file to write the scanner to, `-` for the standard output, known
+    /// after `generate` applied the `%option`s
+    pub fn outfile(&self) -> &str {
+        self.last(|option| match option {
+            LersOption::Outfile(outfile) => Some(outfile.as_str()),
+            LersOption::Stdout(true) => Some("-"),
+            _ => None,
+        })
+        .unwrap_or("lers.yy.c")
+    }
+
+    /// the file to write the declarations of the scanner to, known after
+    /// `generate` applied the `%option`s
+    pub fn header_file(&self) -> Option<&str> {
+        self.last(|option| match option {
+            LersOption::HeaderFile(header_file) => Some(header_file.as_str()),
+            _ => None,
+        })
+    }
+
+    /// the declarations of the scanner for the other C files of a program,
+    /// called after `generate`
+    pub fn generate_header(&self) -> String {
+        let guard = format!("{}_LEX_H", self.prefix().to_uppercase());
+        let mut code = format!("#ifndef {}\n#define {}\n\n", guard, guard);
+        code.push_str(&self.gen_prefix());
+        if let Some(header) = self.bison_header() {
+            code.push_str(&format!("#include \"{}\"\n", header));
+        }
+        code.push_str(HEADER_TYPES);
+        if self.reentrant() {
+            if let Some(extra_type) = self.extra_type() {
+                code.push_str(&format!("#define YY_EXTRA_TYPE {}\n", extra_type));
             }
+            code.push_str(REENTRANT_SCANNER);
+        } else {
+            code.push_str(GLOBAL_SCANNER);
         }
+        if self.locations() {
+            code.push_str(LOCATION_TYPE);
+        }
+        // the variables are only reached through the scanner when it is reentrant
+        if !self.reentrant() {
+            code.push_str("extern FILE *yyin;\nextern FILE *yyout;\n");
+            if self.yytext_array() {
+                code.push_str("extern char yytext[];\n");
+            } else {
+                code.push_str("extern char *yytext;\n");
+            }
+            code.push_str("extern IdxType yyleng;\n");
+            if self.yylineno() {
+                code.push_str("extern int yylineno;\n");
+            }
+            if self.locations() {
+                code.push_str("extern struct yy_location yylocation;\n");
+            }
+            code.push('\n');
+        }
+        code.push_str(HEADER_BUFFER);
+        if self.reentrant() {
+            code.push_str(HEADER_REENTRANT);
+            if self.yylineno() {
+                code.push_str("int yyget_lineno(yyscan_t yyscanner);\n");
+                code.push_str("void yyset_lineno(int line, yyscan_t yyscanner);\n");
+            }
+            code.push('\n');
+        }
+        if self.bison_bridge() {
+            code.push_str(BISON_YYSTYPE);
+            if self.bison_locations() {
+                code.push_str(BISON_YYLTYPE);
+            }
+            code.push('\n');
+        }
+        code.push_str(&format!("{};\n", self.yylex_signature()));
+        if self.yywrap() {
+            code.push_str("int yywrap(YY_ONLY_ARG);\n");
+        }
+        code.push('\n');
+        // the declarations above keep the prefixed names, the macros go
+        code.push_str("#ifndef YY_HEADER_NO_UNDEFS\n");
+        let mut macros = self.prefix_names();
+        macros.extend_from_slice(HEADER_MACROS);
+        if self.reentrant() {
+            macros.push("YY_EXTRA_TYPE");
+        }
+        for name in macros {
+            code.push_str(&format!("#undef {}\n", name));
+        }
+        code.push_str("#endif\n\n");
+        // INITIAL is the same in every scanner, the others may clash
+        code.push_str("#ifdef YY_HEADER_EXPORT_START_CONDITIONS\n");
+        code.push_str(gen_start_conditions(&self.start_conditions().0).trim_end());
+        code.push_str("\n#endif\n\n");
+        code.push_str(&format!("#endif /* {} */\n", guard));
+        code
     }
 
-    fn gen_rule_code(&self) -> String {
+    fn apply_options(&mut self) {
+        let mut file_options = Vec::new();
+        let def_node = self.ast.definition_node.as_ref().unwrap();
+        match def_node.yytext_type {
+            Some("array") => file_options.push(LersOption::Array),
+            Some("pointer") => file_options.push(LersOption::Pointer),
+            _ => {}
+        }
+        if let Some(options) = &def_node.options {
+            file_options.extend(
+                options
+                    .iter()
+                    .filter_map(|option| LersOption::parse(option.name, option.value)),
+            );
+        }
+        // options added before generating come later, so they win
+        self.options.splice(0..0, file_options);
+    }
+
+    // the last option giving a value wins, so later `%option`s override
+    // earlier ones and the command line overrides the file
+    fn last<'s, T>(&'s self, value: impl Fn(&'s LersOption) -> Option<T>) -> Option<T> {
+        self.options.iter().rev().find_map(value)
+    }
+
+    // tables are compressed by default
+    fn full_table(&self) -> bool {
+        self.last(|option| match option {
+            LersOption::Full => Some(true),
+            LersOption::Ecs => Some(false),
+            _ => None,
+        })
+        .unwrap_or(false)
+    }
+
+    // yytext is a pointer by default
+    fn yytext_array(&self) -> bool {
+        self.last(|option| match option {
+            LersOption::Array => Some(true),
+            LersOption::Pointer => Some(false),
+            _ => None,
+        })
+        .unwrap_or(false)
+    }
+
+    fn yywrap(&self) -> bool {
+        last_flag!(self, Yywrap).unwrap_or(!self.main_function())
+    }
+
+    fn main_function(&self) -> bool {
+        last_flag!(self, Main).unwrap_or(false)
+    }
+
+    fn yylineno(&self) -> bool {
+        last_flag!(self, Yylineno).unwrap_or(false) || self.locations()
+    }
+
+    fn locations(&self) -> bool {
+        last_flag!(self, Locations).unwrap_or(false)
+    }
+
+    fn unput(&self) -> bool {
+        last_flag!(self, Unput).unwrap_or(true)
+    }
+
+    fn input(&self) -> bool {
+        last_flag!(self, Input).unwrap_or(true)
+    }
+
+    // unmatched characters are echoed unless nodefault
+    fn default_rule(&self) -> bool {
+        last_flag!(self, Default).unwrap_or(true)
+    }
+
+    fn bison_bridge(&self) -> bool {
+        last_flag!(self, BisonBridge).unwrap_or(false) || self.bison_locations()
+    }
+
+    fn bison_locations(&self) -> bool {
+        last_flag!(self, BisonLocations).unwrap_or(false)
+    }
+
+    fn bison_header(&self) -> Option<&str> {
+        self.last(|option| match option {
+            LersOption::BisonHeader(header) => Some(header.as_str()),
+            _ => None,
+        })
+    }
+
+    // REJECT needs every pattern accepted on the way kept, which costs time and
+    // space, so it is only supported when an action uses it
+    fn uses_reject(&self) -> bool {
+        self.rule_table
+            .actions
+            .iter()
+            .any(|action| contains_identifier(action, "REJECT"))
+    }
+
+    // the symbols start with yy by default
+    fn prefix(&self) -> &str {
+        self.last(|option| match option {
+            LersOption::Prefix(prefix) => Some(prefix.as_str()),
+            _ => None,
+        })
+        .unwrap_or("yy")
+    }
+
+    fn interactive(&self) -> bool {
+        last_flag!(self, Interactive).unwrap_or(true)
+    }
+
+    fn always_interactive(&self) -> bool {
+        last_flag!(self, AlwaysInteractive).unwrap_or(false)
+    }
+
+    fn never_interactive(&self) -> bool {
+        last_flag!(self, NeverInteractive).unwrap_or(false)
+    }
+
+    fn unistd(&self) -> bool {
+        last_flag!(self, Unistd).unwrap_or(true)
+    }
+
+    fn reentrant(&self) -> bool {
+        last_flag!(self, Reentrant).unwrap_or(false)
+    }
+
+    fn extra_type(&self) -> Option<&str> {
+        self.last(|option| match option {
+            LersOption::ExtraType(extra_type) => Some(extra_type.as_str()),
+            _ => None,
+        })
+    }
+
+    // the states of a match are recorded to find the heads of variable trailing
+    // contexts and shorter matches for REJECT
+    fn uses_state_buf(&self, dfa: &Dfa) -> bool {
+        self.uses_reject() || dfa.states.iter().any(|state| !state.head_ends.is_empty())
+    }
+
+    // the state of the scanner is global, or kept in struct yyguts_t for a
+    // reentrant scanner, with macros naming the fields like the globals
+    fn gen_variables(&self, dfa: &Dfa) -> String {
+        let mut variables = Vec::new();
+        if self.reentrant() {
+            variables.push(REENTRANT_VARIABLES);
+        }
+        variables.push(BUFFER_VARIABLES);
+        variables.push(if self.yytext_array() {
+            YYTEXT_ARRAY_VARIABLES
+        } else {
+            YYTEXT_POINTER_VARIABLES
+        });
+        if self.uses_state_buf(dfa) {
+            variables.push(STATE_BUF_VARIABLES);
+        }
+        if self.locations() {
+            variables.push(LOCATION_VARIABLES);
+        } else if self.yylineno() {
+            variables.push(LINENO_VARIABLES);
+        }
+        if self.uses_reject() {
+            variables.push(REJECT_VARIABLES);
+        }
+        if self.reentrant() {
+            gen_guts(&variables)
+        } else {
+            gen_globals(&variables)
+        }
+    }
+
+    // the runtime is written with the yy names, the exported ones are renamed
+    // to the prefix by macros so that several scanners link into one program,
+    // everything else is static
+    fn gen_prefix(&self) -> String {
+        let names = self.prefix_names();
+        if names.is_empty() {
+            return String::new();
+        }
+        let prefix = self.prefix();
         let mut code = String::new();
-        code.push_str(PREPARE);
-        code.push_str(&self.gen_pattern_array());
-        code.push_str(&self.gen_action_function());
-        code.push_str(REGEX);
-        code.push_str(YYLEX);
+        for name in names {
+            code.push_str(&format!("#define {} {}{}\n", name, prefix, &name[2..]));
+        }
+        code.push('\n');
         code
     }
 
-    fn gen_pattern_array(&self) -> String {
+    // the names renamed to the prefix, none with the yy prefix
+    fn prefix_names(&self) -> Vec<&'static str> {
+        let prefix = self.prefix();
+        if prefix == "yy" {
+            return Vec::new();
+        }
+        if !is_c_identifier(prefix) {
+            panic!("Invalid prefix: {}", prefix);
+        }
+        let mut names = PREFIX_FUNCTIONS.to_vec();
+        // the variables are fields of the scanner when it is reentrant
+        if !self.reentrant() {
+            names.extend_from_slice(PREFIX_VARIABLES);
+        }
+        // yywrap is a macro with noyywrap
+        if self.yywrap() {
+            names.push("yywrap");
+        }
+        names
+    }
+
+    fn gen_rule_code(&self) -> String {
+        let patterns = self.parse_patterns();
+        let (names, conditions) = self.start_conditions();
+        let dfa = build_dfa(&patterns, &conditions, self.uses_reject());
         let mut code = String::new();
-        code.push_str(&format!(
-            "#define g_pattern_count {}\n",
-            self.rule_table.pair_count + 1
-        ));
-        code.push_str("char *g_patterns[] = {\n");
-        for pattern in &self.rule_table.patterns {
-            let escaped_pattern = pattern.replace("\\", "\\\\");
-            code.push_str(&format!("  \"{}\",\n", escaped_pattern));
+        if let Some(header) = self.bison_header() {
+            code.push_str(&format!("#include \"{}\"\n", header));
+        }
+        if !self.unistd() {
+            code.push_str("#define YY_NO_UNISTD_H\n");
+        }
+        code.push_str(PREPARE);
+        if self.reentrant()
+            && let Some(extra_type) = self.extra_type()
+        {
+            code.push_str(&format!("#define YY_EXTRA_TYPE {}\n", extra_type));
+        }
+        code.push_str(if self.reentrant() {
+            REENTRANT_SCANNER
+        } else {
+            GLOBAL_SCANNER
+        });
+        if self.locations() {
+            code.push_str(LOCATION_TYPE);
+        }
+        code.push_str(&self.gen_variables(&dfa));
+        // whether the buffers read a line at a time, isatty decides otherwise
+        if self.always_interactive() {
+            code.push_str("#define YY_ALWAYS_INTERACTIVE\n");
+        } else if self.never_interactive() {
+            code.push_str("#define YY_NEVER_INTERACTIVE\n");
+        }
+        code.push_str(BUFFER);
+        code.push_str(if self.yytext_array() {
+            YYTEXT_ARRAY
+        } else {
+            YYTEXT_POINTER
+        });
+        // called at the end of input, the user provides yywrap unless noyywrap
+        if !self.yywrap() {
+            code.push_str("#define yywrap(yyscanner) 1\n");
+        } else if self.reentrant() {
+            code.push_str("int yywrap(yyscan_t yyscanner);\n");
+        } else {
+            code.push_str("int yywrap(void);\n");
         }
-        // match unmatched characters
-        code.push_str("  \".|\\\\n\",\n");
-        code.push_str("};\n");
         code.push('\n');
+        code.push_str(&gen_start_conditions(&names));
+        code.push_str(&self.gen_tables(&dfa));
+        code.push_str(&self.gen_trailing_context(&patterns));
+        code.push_str(&self.gen_lineno(&patterns));
+        if self.uses_reject() {
+            code.push_str(REJECT);
+        }
+        code.push_str(MATCH);
+        code.push_str(&self.gen_action_helpers());
+        if self.reentrant() {
+            code.push_str(REENTRANT);
+        }
+        code.push_str(&self.gen_bison_bridge());
+        code.push_str(&self.gen_yylex(&names));
+        if self.bison_bridge() {
+            code.push_str("\n#undef yylval\n");
+            if self.bison_locations() {
+                code.push_str("#undef yylloc\n");
+            }
+        }
+        if self.main_function() {
+            code.push_str(if self.reentrant() {
+                REENTRANT_MAIN
+            } else {
+                MAIN
+            });
+        }
         code
     }
 
-    fn gen_action_function(&self) -> String {
+    fn parse_patterns(&self) -> Vec<Pattern> {
+        let mut patterns = self
+            .rule_table
+            .patterns
+            .iter()
+            .map(|pattern| regex::parse(pattern))
+            .collect::<Vec<_>>();
+        // match unmatched characters
+        patterns.push(regex::parse(".|\\n"));
+        patterns
+    }
+
+    // the names of the start conditions, INITIAL first, and the indices of the
+    // rules active in every start condition
+    fn start_conditions(&self) -> (Vec<&'a str>, Vec<Vec<usize>>) {
+        let mut names = vec!["INITIAL"];
+        let mut exclusive = vec![false];
+        if let Some(def_node) = &self.ast.definition_node
+            && let Some(declarations) = &def_node.start_conditions
+        {
+            for declaration in declarations {
+                if !names.contains(&declaration.name) {
+                    names.push(declaration.name);
+                    exclusive.push(declaration.exclusive);
+                }
+            }
+        }
+
+        let mut conditions = vec![Vec::new(); names.len()];
+        for (index, rule_conditions) in self.rule_table.start_conditions.iter().enumerate() {
+            if rule_conditions.is_empty() {
+                // active in the inclusive start conditions
+                for (condition, &exclusive) in exclusive.iter().enumerate() {
+                    if !exclusive {
+                        conditions[condition].push(index);
+                    }
+                }
+            }
+            for &name in rule_conditions {
+                if name == "*" {
+                    conditions.iter_mut().for_each(|rules| rules.push(index));
+                    continue;
+                }
+                let condition = condition_index(&names, name, self.rule_table.patterns[index]);
+                conditions[condition].push(index);
+            }
+        }
+        // unmatched characters are echoed in every start condition
+        for rules in &mut conditions {
+            rules.sort_unstable();
+            rules.dedup();
+            rules.push(self.rule_table.pair_count);
+        }
+        (names, conditions)
+    }
+
+    // the index of the <<EOF>> rule of every start condition, a rule without
+    // start conditions applies to those without their own <<EOF>> rule
+    fn eof_rules(&self, names: &[&str]) -> Vec<Option<usize>> {
+        let mut rules = vec![None; names.len()];
+        let conditions = &self.rule_table.eof_start_conditions;
+        for (index, rule_conditions) in conditions.iter().enumerate() {
+            for &name in rule_conditions {
+                let matched = if name == "*" {
+                    (0..names.len()).collect()
+                } else {
+                    vec![condition_index(names, name, EOF_PATTERN)]
+                };
+                for condition in matched {
+                    if rules[condition].is_some() {
+                        panic!(
+                            "multiple <<EOF>> rules for start condition {}",
+                            names[condition]
+                        );
+                    }
+                    rules[condition] = Some(index);
+                }
+            }
+        }
+        if let Some(index) = conditions.iter().position(|c| c.is_empty()) {
+            for rule in rules.iter_mut().filter(|rule| rule.is_none()) {
+                *rule = Some(index);
+            }
+        }
+        rules
+    }
+
+    fn gen_tables(&self, dfa: &Dfa) -> String {
         let mut code = String::new();
-        code.push_str("void action(int pattern_index) {\n");
-        for (i, action) in self.rule_table.actions.iter().enumerate() {
+        code.push_str(&format!("#define g_state_count {}\n", dfa.states.len()));
+        code.push_str(&format!("#define YY_DEAD_STATE {}\n", DEAD));
+        code.push('\n');
+
+        // start states in the middle of a line and at the beginning of a line,
+        // for every start condition
+        code.push_str(&gen_array("g_start_states", &dfa.starts));
+
+        // states with every transition to the dead state, so that a token
+        // typed on a terminal is matched without waiting for the next line
+        if self.interactive() {
+            let dead_ends = dfa
+                .states
+                .iter()
+                .map(|state| state.next.iter().all(|&next| next == DEAD) as usize)
+                .collect::<Vec<_>>();
+            code.push_str(&gen_array("g_dead_ends", &dead_ends));
+            code.push_str("#define yy_dead_end(state) (g_dead_ends[state])\n");
+        } else {
+            code.push_str("#define yy_dead_end(state) 0\n");
+        }
+        code.push('\n');
+
+        if self.full_table() {
+            // next state of every state for every byte
             code.push_str(&format!(
-                "  if (pattern_index == {}) {{\n{}\n  }}\n",
-                i, action
+                "static const {} g_transitions[g_state_count][256] = {{\n",
+                c_int_type(dfa.states.len())
             ));
+            for state in &dfa.states {
+                code.push_str("  {\n");
+                code.push_str(&gen_numbers(&state.next, 4));
+                code.push_str("  },\n");
+            }
+            code.push_str("};\n");
+            code.push('\n');
+            code.push_str(NEXT_STATE_FULL);
+        } else {
+            // next state of every state for every equivalence class of bytes
+            let classes = dfa.equivalence_classes();
+            let table = CompressedTable::new(dfa, &classes);
+            code.push_str(&gen_array("g_ec", &classes));
+            code.push_str(&gen_array("g_base", &table.base));
+            code.push_str(&gen_array("g_default", &table.default));
+            code.push_str(&gen_array("g_next", &table.next));
+            code.push_str(&gen_array("g_check", &table.check));
+            code.push_str(NEXT_STATE_COMPRESSED);
+        }
+        code.push('\n');
+
+        // pattern index plus one of every state, zero if nothing is accepted,
+        // REJECT takes the patterns from the lists below instead
+        if !self.uses_reject() {
+            let accepts = dfa
+                .states
+                .iter()
+                .map(|state| state.accept.map_or(0, |index| index + 1))
+                .collect::<Vec<_>>();
+            code.push_str(&gen_array("g_accepts", &accepts));
+        }
+
+        // the states after every character of the match
+        let head_ends = dfa.states.iter().any(|state| !state.head_ends.is_empty());
+        if self.uses_state_buf(dfa) {
+            code.push_str("#define YY_USES_STATE_BUF\n");
+            code.push_str(STATE_BUF);
+        }
+
+        // the rules with variable trailing context whose heads end in every state
+        if head_ends {
+            let mut bases = vec![0];
+            let mut head_ends = Vec::new();
+            for state in &dfa.states {
+                head_ends.extend(&state.head_ends);
+                bases.push(head_ends.len());
+            }
+            code.push_str("#define YY_USES_HEAD_ENDS\n");
+            code.push_str(&gen_array("g_head_end_bases", &bases));
+            code.push_str(&gen_array("g_head_ends", &head_ends));
+            code.push_str(HEAD_END);
         }
-        // echo unmatched characters
+
+        // the same before a newline or the end of input, for patterns ending with $
+        let eol = dfa.states.iter().any(|state| {
+            state.eol_accept != state.accept || state.eol_accept_list != state.accept_list
+        });
+        if eol {
+            code.push_str("#define YY_USES_EOL\n");
+        }
+        if eol && !self.uses_reject() {
+            let eol_accepts = dfa
+                .states
+                .iter()
+                .map(|state| state.eol_accept.map_or(0, |index| index + 1))
+                .collect::<Vec<_>>();
+            code.push_str(&gen_array("g_eol_accepts", &eol_accepts));
+        }
+
+        // every pattern accepted in every state, the lists before a newline or
+        // the end of input follow the others
+        if self.uses_reject() {
+            let mut accept_list = Vec::new();
+            let mut bases = vec![0];
+            for state in &dfa.states {
+                accept_list.extend(&state.accept_list);
+                bases.push(accept_list.len());
+            }
+            code.push_str("#define YY_USES_REJECT\n");
+            code.push_str(&gen_array("g_accept_bases", &bases));
+            if eol {
+                let mut eol_bases = vec![accept_list.len()];
+                for state in &dfa.states {
+                    accept_list.extend(&state.eol_accept_list);
+                    eol_bases.push(accept_list.len());
+                }
+                code.push_str(&gen_array("g_eol_accept_bases", &eol_bases));
+            }
+            code.push_str(&gen_array("g_accept_list", &accept_list));
+        }
+        code
+    }
+
+    // cut the trailing context off the match, the length of the head is known
+    // when either part has a fixed length, or found with the recorded states
+    fn gen_trailing_context(&self, patterns: &[Pattern]) -> String {
+        if patterns.iter().all(|pattern| pattern.trailing.is_none()) {
+            return String::new();
+        }
+        let mut code = String::new();
+        code.push_str("#define YY_USES_TRAILING_CONTEXT\n");
+        code.push_str(
+            "static IdxType yy_trailing_context(int pattern_index, IdxType len YY_LAST_ARG) {\n",
+        );
+        // the scanner is only used to find the head with the recorded states
+        code.push_str("  YY_DECL_GUTS_VAR();\n");
+        code.push_str("  switch (pattern_index) {\n");
+        for (i, pattern) in patterns.iter().enumerate() {
+            let Some(trailing) = &pattern.trailing else {
+                continue;
+            };
+            let len = match (pattern.regex.fixed_length(), trailing.fixed_length()) {
+                (Some(head), _) => head.to_string(),
+                (None, Some(trailing)) => format!("len - {}", trailing),
+                (None, None) => {
+                    eprintln!(
+                        "warning: dangerous trailing context in rule \"{}\", \
+                         the head may not be split correctly as both parts have variable length",
+                        self.rule_table.patterns[i]
+                    );
+                    format!("yy_head_end({}, len YY_CALL_LAST_ARG)", i)
+                }
+            };
+            code.push_str(&format!("  case {}:\n    return {};\n", i, len));
+        }
+        code.push_str("  }\n");
+        code.push_str("  return len;\n");
+        code.push_str("}\n");
+        code.push('\n');
+        code
+    }
+
+    // yylineno is only updated after the rules which can match a newline,
+    // unless the columns are counted as well
+    fn gen_lineno(&self, patterns: &[Pattern]) -> String {
+        let mut code = String::new();
+        if self.locations() {
+            code.push_str("#define YY_USES_LINENO\n");
+            code.push_str(LOCATION);
+        } else if self.yylineno() {
+            let newline_rules = patterns
+                .iter()
+                .map(|pattern| pattern.regex.may_contain(b'\n') as usize)
+                .collect::<Vec<_>>();
+            code.push_str("#define YY_USES_LINENO\n");
+            code.push_str(&gen_array("g_newline_rules", &newline_rules));
+            code.push_str(LINENO);
+        }
+        code
+    }
+
+    // yyless, yymore, unput and input for the actions, nounput and noinput
+    // leave the last two out
+    fn gen_action_helpers(&self) -> String {
+        let mut code = String::new();
+        if !self.unput() {
+            code.push_str("#define YY_NO_UNPUT\n");
+        }
+        if !self.input() {
+            code.push_str("#define YY_NO_INPUT\n");
+        }
+        code.push_str(ACTION_HELPERS);
+        code
+    }
+
+    // pure bison parsers pass the semantic value and the location to fill in,
+    // actions fill them in through the pointers yylval and yylloc, as in flex
+    fn gen_bison_bridge(&self) -> String {
+        if !self.bison_bridge() {
+            return String::new();
+        }
+        let mut code = String::from("\n");
+        code.push_str("#define YY_BISON_BRIDGE\n");
+        code.push_str(BISON_YYSTYPE);
+        if self.bison_locations() {
+            code.push_str("#define YY_BISON_LOCATIONS\n");
+            code.push_str(BISON_YYLTYPE);
+        }
+        code.push_str(&format!("#define YY_DECL {}\n", self.yylex_signature()));
+        if self.bison_locations() {
+            code.push_str("#define yylloc yylloc_param\n");
+        }
+        code.push_str("#define yylval yylval_param\n");
+        code
+    }
+
+    fn yylex_signature(&self) -> &'static str {
+        if self.bison_locations() {
+            "int yylex(YYSTYPE *yylval_param, YYLTYPE *yylloc_param YY_LAST_ARG)"
+        } else if self.bison_bridge() {
+            "int yylex(YYSTYPE *yylval_param YY_LAST_ARG)"
+        } else {
+            "int yylex(YY_ONLY_ARG)"
+        }
+    }
+
+    // actions are inlined in yylex, so a `return` in them returns the token
+    fn gen_yylex(&self, names: &[&str]) -> String {
+        let mut code = String::new();
+        // the <<EOF>> rules come after the default rule
+        let eof_base = self.rule_table.pair_count + 1;
         code.push_str(&format!(
-            "  if (pattern_index == {}) {{ fwrite(yytext, (size_t) yyleng, 1, yyout); }}\n",
-            self.rule_table.actions.len()
+            "\n#define YY_STATE_EOF(condition) ({} + (condition))\n",
+            eof_base
         ));
-        code.push_str("}\n");
+        code.push_str(YYLEX_START);
+        let actions = &self.rule_table.actions;
+        for (i, action) in actions.iter().enumerate() {
+            code.push_str(&format!("    case {}:\n", i));
+            // rules joined by `|` share the action written once
+            if actions
+                .get(i + 1)
+                .is_some_and(|next| std::ptr::eq(*next, *action))
+            {
+                continue;
+            }
+            code.push_str(&format!("{}\n      break;\n", action));
+        }
+        // echo unmatched characters, or stop with nodefault
+        code.push_str(&format!(
+            "    case {}:\n      {};\n      break;\n",
+            actions.len(),
+            if self.default_rule() {
+                "ECHO"
+            } else {
+                "YY_FATAL_ERROR(\"scanner jammed\")"
+            }
+        ));
+        let eof_rules = self.eof_rules(names);
+        for (i, action) in self.rule_table.eof_actions.iter().enumerate() {
+            for (condition, _) in eof_rules.iter().enumerate().filter(|(_, r)| **r == Some(i)) {
+                code.push_str(&format!(
+                    "    case {}: /* <<EOF>> in {} */\n",
+                    eof_base + condition,
+                    names[condition]
+                ));
+            }
+            code.push_str(&format!("{}\n      break;\n", action));
+        }
+        code.push_str(YYLEX_END);
         code
     }
 }
 
+// compile all patterns into one DFA, so the scanner needs no regex engine
+fn build_dfa(patterns: &[Pattern], conditions: &[Vec<usize>], reject: bool) -> Dfa {
+    Dfa::from_nfa(&Nfa::from_rules(patterns, conditions), reject).minimize()
+}
+
+// whether the name appears in the text, not as a part of a longer identifier
+fn contains_identifier(text: &str, name: &str) -> bool {
+    let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices(name).any(|(i, _)| {
+        !text[..i].ends_with(is_identifier) && !text[i + name.len()..].starts_with(is_identifier)
+    })
+}
+
+// the number of the start condition named in a rule
+fn condition_index(names: &[&str], name: &str, pattern: &str) -> usize {
+    names.iter().position(|&n| n == name).unwrap_or_else(|| {
+        panic!(
+            "undeclared start condition {} in rule \"{}\"",
+            name, pattern
+        )
+    })
+}
+
+// number the start conditions for BEGIN
+fn gen_start_conditions(names: &[&str]) -> String {
+    let mut code = String::new();
+    for (i, name) in names.iter().enumerate() {
+        code.push_str(&format!("#define {} {}\n", name, i));
+    }
+    code.push('\n');
+    code
+}
+
+// the smallest C integer type holding every number below max
+fn c_int_type(max: usize) -> &'static str {
+    if max <= i8::MAX as usize {
+        "signed char"
+    } else if max <= i16::MAX as usize {
+        "short"
+    } else {
+        "int"
+    }
+}
+
+fn is_c_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// a constant array of numbers with the smallest element type
+fn gen_array(name: &str, numbers: &[usize]) -> String {
+    let max = numbers.iter().max().map_or(0, |&max| max + 1);
+    format!(
+        "static const {} {}[{}] = {{\n{}}};\n\n",
+        c_int_type(max),
+        name,
+        numbers.len(),
+        gen_numbers(numbers, 2)
+    )
+}
+
+// comma separated numbers, sixteen in a line
+fn gen_numbers(numbers: &[usize], indent: usize) -> String {
+    let mut code = String::new();
+    for line in numbers.chunks(16) {
+        code.push_str(&" ".repeat(indent));
+        let line = line.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        code.push_str(&line.join(", "));
+        code.push_str(",\n");
+    }
+    code
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use std::{env, fs};
+
+    // where a test scanner reads its input from
+    enum Input<'s> {
+        // the name of a file holding it, as the first argument
+        File(&'s str),
+        Stdin(&'s str),
+        // the scanner reads from elsewhere, as a string in memory
+        None,
+    }
+
+    // compile the scanner of the lex source with the C compiler, warnings as
+    // errors, and run it on the input, None when there is no C compiler
+    fn run_scanner(name: &str, source: &str, cflags: &[&str], input: Input) -> Option<String> {
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        let dir = env::temp_dir().join(format!("lers-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (c_file, binary, input_file) = (
+            dir.join("lers.yy.c"),
+            dir.join("scanner"),
+            dir.join("input.txt"),
+        );
+        fs::write(&c_file, code).unwrap();
+        let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let Ok(compiled) = Command::new(compiler)
+            .args(["-Wall", "-Wextra", "-Werror"])
+            .args(cflags)
+            .arg(&c_file)
+            .arg("-o")
+            .arg(&binary)
+            .output()
+        else {
+            fs::remove_dir_all(&dir).unwrap();
+            return None;
+        };
+        assert!(
+            compiled.status.success(),
+            "{}",
+            String::from_utf8_lossy(&compiled.stderr)
+        );
+        let mut command = Command::new(&binary);
+        command.stdout(Stdio::piped());
+        match input {
+            Input::File(input) => {
+                fs::write(&input_file, input).unwrap();
+                command.arg(&input_file).stdin(Stdio::null());
+            }
+            Input::Stdin(_) => {
+                command.stdin(Stdio::piped());
+            }
+            Input::None => {
+                command.stdin(Stdio::null());
+            }
+        }
+        let mut child = command.spawn().unwrap();
+        if let Input::Stdin(input) = input {
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(input.as_bytes())
+                .unwrap();
+        }
+        let output = child.wait_with_output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(output.status.success());
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
 
     #[test]
     fn codegen() {
@@ -149,49 +1028,792 @@ pattern3    { action3(); }
 
 %%
 void helper() {}"#;
-        let mut codegen = CodeGen::new(Parser::new(Lexer::new(&source)).parse());
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
         let rule_table = RuleTable {
             pair_count: 3,
-            patterns: vec!["[0-9]+", "[0-9]+", "pattern3"],
+            patterns: vec!["([0-9])+", "(([0-9])+)", "pattern3"],
             actions: vec!["{ action1(); }", "{ action2(); }", "{ action3(); }"],
+            start_conditions: vec![Vec::new(); 3],
+            ..Default::default()
         };
         let code = codegen.generate();
-        assert_eq!(code, target_code());
+        let (names, conditions) = codegen.start_conditions();
+        assert_eq!(names, vec!["INITIAL"]);
+        let dfa = build_dfa(&codegen.parse_patterns(), &conditions, false);
+        // dead, start, digits, "p" to "pattern3", and other characters
+        assert_eq!(dfa.states.len(), 12);
+        assert_eq!(code, target_code(&codegen.gen_tables(&dfa)));
         assert_eq!(codegen.rule_table, rule_table);
-        assert_eq!(codegen.options, vec![lers_option::LersOption::Noyywrap]);
+        assert_eq!(codegen.options, vec![LersOption::Yywrap(false)]);
+        assert!(!codegen.full_table());
+        assert!(!codegen.yytext_array());
+    }
+
+    #[test]
+    fn input_buffer() {
+        let source = include_str!("../../examples/wc.l");
+        let input = "hello world\nfoo  bar baz\n".repeat(100);
+        // a buffer of 2 bytes is refilled and enlarged for every token
+        for cflags in [&[][..], &["-DYY_BUF_SIZE=2"]] {
+            let Some(output) = run_scanner("wc", source, cflags, Input::File(&input)) else {
+                return;
+            };
+            assert!(output.starts_with("  line  word  byte  file\n   200   500  2500  "));
+        }
+    }
+
+    #[test]
+    fn interactive() {
+        let source = include_str!("../../examples/wc.l");
+        let input = "hello world\nfoo  bar baz\n".repeat(100);
+        for (options, defined) in [
+            ("always-interactive", "#define YY_ALWAYS_INTERACTIVE\n"),
+            ("batch never-interactive", "#define YY_NEVER_INTERACTIVE\n"),
+            // neither unistd.h nor isatty, the input is read in blocks
+            ("nounistd", "#define YY_NO_UNISTD_H\n#include <stdio.h>\n"),
+        ] {
+            let source = format!("%option {}\n{}", options, source);
+            let code = CodeGen::new(Parser::new(Lexer::new(&source)).parse()).generate();
+            assert!(code.contains(defined));
+            // a batch scanner reads ahead after every token
+            let batch = options.starts_with("batch");
+            assert_eq!(code.contains("g_dead_ends"), !batch);
+            assert_eq!(code.contains("#define yy_dead_end(state) 0\n"), batch);
+            let name = format!("wc-{}", options.replace(' ', "-"));
+            let Some(output) = run_scanner(&name, &source, &[], Input::File(&input)) else {
+                return;
+            };
+            assert!(output.starts_with("  line  word  byte  file\n   200   500  2500  "));
+        }
+    }
+
+    #[test]
+    fn yy_input() {
+        let source = r#"%option noyywrap main
+%{
+/* one byte at a time from a string */
+static const char *g_source = "ab cd\nef";
+#define YY_INPUT(buf, result, max_size) \
+  (result) = *g_source ? (*(buf) = *g_source++, 1) : 0;
+%}
+%%
+[a-z]+  { printf("<%s>", yytext); }
+%%
+"#;
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        // the default is only defined when the definitions do not
+        let defined = code.find("#define YY_INPUT(buf, result, max_size) \\\n");
+        assert!(defined.unwrap() < code.find("#ifndef YY_INPUT\n").unwrap());
+        if let Some(output) = run_scanner("yy_input", source, &[], Input::None) {
+            assert_eq!(output, "<ab> <cd>\n<ef>");
+        }
+    }
+
+    #[test]
+    fn scan_memory() {
+        let source = r#"%option noyywrap prefix="mem"
+%%
+[a-z]+  { printf("<%s>", yytext); }
+%%
+int main(void) {
+  char buffer[] = "gh ij\0\0";
+  YY_BUFFER_STATE string = yy_scan_string("ab cd");
+  yylex();
+  yy_delete_buffer(string);
+  YY_BUFFER_STATE bytes = yy_scan_bytes("ef\0xx", 2);
+  yylex();
+  yy_delete_buffer(bytes);
+  /* scanned in place, the last two bytes are the end */
+  YY_BUFFER_STATE in_place = yy_scan_buffer(buffer, sizeof(buffer) - 1);
+  yylex();
+  yy_delete_buffer(in_place);
+  return 0;
+}"#;
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        for (function, name) in [
+            (
+                "YY_BUFFER_STATE yy_scan_string(const char *str YY_LAST_ARG) {",
+                "yy_scan_string",
+            ),
+            (
+                "YY_BUFFER_STATE yy_scan_bytes(const char *bytes, IdxType len YY_LAST_ARG) {",
+                "yy_scan_bytes",
+            ),
+            (
+                "YY_BUFFER_STATE yy_scan_buffer(char *base, IdxType size YY_LAST_ARG) {",
+                "yy_scan_buffer",
+            ),
+            (
+                "void yy_delete_buffer(YY_BUFFER_STATE buffer YY_LAST_ARG) {",
+                "yy_delete_buffer",
+            ),
+        ] {
+            assert!(code.contains(function));
+            assert!(code.contains(&format!("#define {} mem{}\n", name, &name[2..])));
+        }
+        if let Some(output) = run_scanner("scan_memory", source, &[], Input::None) {
+            assert_eq!(output, "<ab> <cd><ef><gh> <ij>");
+        }
+    }
+
+    #[test]
+    fn buffer_stack() {
+        let source = r#"%option noyywrap prefix="inc"
+%{
+#include <stdio.h>
+
+/* a file to include, made at every @ */
+static FILE *include(void) {
+  FILE *file = tmpfile();
+  fputs("x y", file);
+  rewind(file);
+  return file;
+}
+%}
+%%
+[a-z]+   { printf("<%s>", yytext); }
+@        { yypush_buffer_state(yy_create_buffer(include(), YY_BUF_SIZE)); }
+<<EOF>>  {
+           if (YY_CURRENT_BUFFER->input != yyin)
+             fclose(YY_CURRENT_BUFFER->input);
+           yypop_buffer_state();
+           if (!YY_CURRENT_BUFFER)
+             yyterminate();
+         }
+.|\n     ;
+%%
+int main(void) {
+  yylex();
+  return 0;
+}"#;
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        for (function, name) in [
+            (
+                "YY_BUFFER_STATE yy_create_buffer(FILE *file, int size YY_LAST_ARG) {",
+                "yy_create_buffer",
+            ),
+            (
+                "void yy_switch_to_buffer(YY_BUFFER_STATE buffer YY_LAST_ARG) {",
+                "yy_switch_to_buffer",
+            ),
+            (
+                "void yypush_buffer_state(YY_BUFFER_STATE buffer YY_LAST_ARG) {",
+                "yypush_buffer_state",
+            ),
+            (
+                "void yypop_buffer_state(YY_ONLY_ARG) {",
+                "yypop_buffer_state",
+            ),
+            ("void yyrestart(FILE *file YY_LAST_ARG) {", "yyrestart"),
+        ] {
+            assert!(code.contains(function));
+            assert!(code.contains(&format!("#define {} inc{}\n", name, &name[2..])));
+        }
+        // the helpers saving and loading the buffers are not exported
+        assert!(code.contains("static void yy_load_buffer_state(YY_ONLY_ARG) {"));
+        if let Some(output) = run_scanner("buffer_stack", source, &[], Input::Stdin("a @ b @\nc")) {
+            assert_eq!(output, "<a><x><y><b><x><y><c>");
+        }
+    }
+
+    #[test]
+    fn trailing_context() {
+        let source = r#"
+%%
+ab/c+       { fixed_head(); }
+a+/bc       { fixed_trailing(); }
+a+/b+c      { variable(); }
+%%"#;
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        let code = codegen.generate();
+        assert!(code.contains(
+            r#"static IdxType yy_trailing_context(int pattern_index, IdxType len YY_LAST_ARG) {
+  YY_DECL_GUTS_VAR();
+  switch (pattern_index) {
+  case 0:
+    return 2;
+  case 1:
+    return len - 2;
+  case 2:
+    return yy_head_end(2, len YY_CALL_LAST_ARG);
+  }
+  return len;
+}
+"#
+        ));
+        assert!(code.contains("#define YY_USES_HEAD_ENDS\n"));
+
+        // the head is the token, the trailing context is scanned again
+        let source = r#"%option noyywrap main
+%%
+ab/c+       { printf("<1:%s>", yytext); }
+a+/bc       { printf("<2:%s>", yytext); }
+a+/b+c      { printf("<3:%s>", yytext); }
+%%
+"#;
+        for cflags in [&[][..], &["-DYY_BUF_SIZE=2"]] {
+            let input = Input::Stdin("abcc aabc aabbc");
+            let Some(output) = run_scanner("trailing_context", source, cflags, input) else {
+                return;
+            };
+            assert_eq!(output, "<1:ab>cc <2:aa>bc <3:aa>bbc");
+        }
     }
 
-    fn target_code() -> String {
+    #[test]
+    fn start_conditions() {
+        let source = r#"
+%s STRING
+%x COMMENT
+%%
+"/*"        { BEGIN(COMMENT); }
+<COMMENT>{
+"*/"        { BEGIN(INITIAL); }
+}
+<STRING>\" { BEGIN(INITIAL); }
+<*>\n      { newline(); }
+%%"#;
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        let code = codegen.generate();
+        assert!(code.contains("#define INITIAL 0\n#define STRING 1\n#define COMMENT 2\n"));
+        let (names, conditions) = codegen.start_conditions();
+        assert_eq!(names, vec!["INITIAL", "STRING", "COMMENT"]);
+        // the default rule is always the last
+        assert_eq!(
+            conditions,
+            vec![vec![0, 3, 4], vec![0, 2, 3, 4], vec![1, 3, 4]]
+        );
+        let dfa = build_dfa(&codegen.parse_patterns(), &conditions, false);
+        assert_eq!(dfa.starts.len(), 6);
+
+        // the unprefixed rules are active in STRING, where "/*" starts a
+        // comment, but not in COMMENT, which hides the words and quotes
+        let source = r#"%option noyywrap main
+%s STRING
+%x COMMENT
+%%
+"/*"            { BEGIN(COMMENT); printf("<c>"); }
+<COMMENT>{
+"*/"            { BEGIN(INITIAL); printf("</c>"); }
+.|\n            ;
+}
+\"              { BEGIN(STRING); printf("<s>"); }
+<STRING>\"      { BEGIN(INITIAL); printf("</s>"); }
+<STRING>[a-z]+  { printf("S%s", yytext); }
+[a-z]+          { printf("W%s", yytext); }
+%%
+"#;
+        let input = Input::Stdin("ab \"cd /* ef\" /* gh \" */ ij");
+        if let Some(output) = run_scanner("start_conditions", source, &[], input) {
+            assert_eq!(output, "Wab <s>Scd <c></c> Wij");
+        }
+    }
+
+    #[test]
+    fn eof_rules() {
+        let source = r#"
+%x COMMENT STRING
+%%
+"/*"                { BEGIN(COMMENT); }
+<COMMENT><<EOF>>    { unterminated(); }
+<<EOF>>             { end(); }
+%%"#;
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        let code = codegen.generate();
+        assert_eq!(codegen.rule_table.patterns, vec!["\"/*\""]);
+        let (names, _) = codegen.start_conditions();
+        assert_eq!(codegen.eof_rules(&names), vec![Some(1), Some(0), Some(1)]);
+        assert!(code.contains("#define YY_STATE_EOF(condition) (2 + (condition))\n"));
+        assert!(code.contains(
+            r#"    case 3: /* <<EOF>> in COMMENT */
+{ unterminated(); }
+      break;
+    case 2: /* <<EOF>> in INITIAL */
+    case 4: /* <<EOF>> in STRING */
+{ end(); }
+      break;
+"#
+        ));
+
+        // the rule of the start condition at the end runs before yyterminate
+        let source = r#"%option noyywrap main
+%x COMMENT
+%%
+"/*"                { BEGIN(COMMENT); }
+<COMMENT>"*/"       { BEGIN(INITIAL); }
+<COMMENT>.|\n       ;
+<COMMENT><<EOF>>    { printf("<unterminated>"); yyterminate(); }
+<<EOF>>             { printf("<end>"); yyterminate(); }
+%%
+"#;
+        for (input, expected) in [("a /* b */ c", "a  c<end>"), ("a /* b", "a <unterminated>")] {
+            let Some(output) = run_scanner("eof_rules", source, &[], Input::Stdin(input)) else {
+                return;
+            };
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn yylineno() {
+        let source = r#"
+%option yylineno
+%%
+[a-z]+      { word(); }
+\n+/x       { newlines(); }
+"%"[^\n]*   { comment(); }
+%%"#;
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(
+            code.contains("static const signed char g_newline_rules[4] = {\n  0, 1, 0, 1,\n};\n")
+        );
+
+        // newlines in comments count, in the middle of a token too
+        let source = r#"%option noyywrap main yylineno
+%%
+[a-z]+                      { printf("%s:%d ", yytext, yylineno); }
+"/*"([^*]|"*"+[^*/])*"*"+"/"  ;
+.|\n                        ;
+%%
+"#;
+        let input = Input::Stdin("a\nb /* x\ny\n */ c\n\nd");
+        if let Some(output) = run_scanner("yylineno", source, &[], input) {
+            assert_eq!(output, "a:1 b:2 c:4 d:6 ");
+        }
+
+        // the first and last line and column of every token, and its offset
+        let source = r#"%option noyywrap main locations
+%%
+[a-z]+  {
+          printf("%s:%d.%d-%d.%d@%lu ", yytext, yylocation.first_line,
+                 yylocation.first_column, yylocation.last_line,
+                 yylocation.last_column, yylocation.offset);
+        }
+.|\n    ;
+%%
+"#;
+        if let Some(output) = run_scanner("locations", source, &[], Input::Stdin("ab\n cd")) {
+            assert_eq!(output, "ab:1.1-1.2@0 cd:2.2-2.3@4 ");
+        }
+    }
+
+    #[test]
+    fn yywrap() {
+        let source = "%%\n%%\nint yywrap(void) { return 1; }";
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(code.contains("int yywrap(void);\n"));
+        assert!(!code.contains("#define yywrap(yyscanner) 1\n"));
+    }
+
+    #[test]
+    fn yytext_type() {
+        let source = "%array\n%%\n%%";
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        assert!(codegen.generate().contains(YYTEXT_ARRAY));
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        codegen.add_option(LersOption::Pointer);
+        assert!(codegen.generate().contains(YYTEXT_POINTER));
+    }
+
+    #[test]
+    fn shared_actions() {
+        let source = r#"
+%%
+"if"    |
+"do"    { keyword(); }
+"fi"    { keyword(); }
+%%"#;
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(code.contains(
+            r#"    case 0:
+    case 1:
+{ keyword(); }
+      break;
+    case 2:
+{ keyword(); }
+      break;
+    case 3:
+      ECHO;
+      break;
+"#
+        ));
+    }
+
+    #[test]
+    fn reject() {
+        let source = r#"
+%%
+frob        { special(); REJECT; }
+[a-z]+      { ++words; }
+%%"#;
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(code.contains("#define YY_USES_REJECT\n"));
+        assert!(code.contains(STATE_BUF));
+        assert!(code.contains(REJECT));
+        // the default rule after one character, and both rules after "frob"
+        assert!(code.contains("  2, 1, 2, 1, 2, 1, 1, 1, 0, 1,\n"));
+        assert!(!code.contains("#define YY_USES_EOL\n"));
+
+        let source = "%%\n[a-z]+    { NOT_REJECT(); }\n%%";
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(!code.contains("#define YY_USES_REJECT\n"));
+        assert!(!code.contains(STATE_BUF));
+
+        // the next rule of the same length, then the shorter matches, with the
+        // lines of the rejected token taken back
+        let source = r#"%option noyywrap main yylineno
+%%
+frob        { printf("<frob>"); REJECT; }
+x\ny        { printf("{%d}", yylineno); REJECT; }
+[a-z]+      { printf("[%s]", yytext); }
+\n          { printf("%d|", yylineno); }
+.           ;
+%%
+"#;
+        for cflags in [&[][..], &["-DYY_BUF_SIZE=2"]] {
+            let input = Input::Stdin("frob x\ny\nfrobs");
+            let Some(output) = run_scanner("reject", source, cflags, input) else {
+                return;
+            };
+            assert_eq!(output, "<frob>[frob]{2}[x]2|[y]3|[frobs]");
+        }
+    }
+
+    #[test]
+    fn action_helpers() {
+        let source = "%option nounput noinput\n%%\n%%";
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(code.contains("#define YY_NO_UNPUT\n#define YY_NO_INPUT\n"));
+
+        // yyless scans the rest of the token again, yymore keeps it in front
+        // of the next one, unput pushes characters back and input takes one
+        let source = r#"%option noyywrap main
+%%
+ab          { printf("[%s]", yytext); yyless(1); }
+b           { printf("<b>"); }
+more        { yymore(); }
+"!"         { printf("(%s)", yytext); }
+u           { unput('x'); unput('y'); }
+i           { printf("{%c}", input()); }
+[xy]        { printf("%s", yytext); }
+%%
+"#;
+        for cflags in [&[][..], &["-DYY_BUF_SIZE=2"]] {
+            let input = Input::Stdin("ab more! u i9");
+            let Some(output) = run_scanner("action_helpers", source, cflags, input) else {
+                return;
+            };
+            assert_eq!(output, "[ab]<b> (more!) yx {9}");
+        }
+    }
+
+    #[test]
+    fn reentrant() {
+        let source = "%option reentrant yylineno\n%%\n%%";
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(code.contains(REENTRANT_SCANNER));
+        assert!(code.contains(
+            "/* the state of a scanner */
+struct yyguts_t {
+  /* the data given to yylex_init_extra */
+  YY_EXTRA_TYPE yyextra;
+  FILE *yyin;
+"
+        ));
+        assert!(code.contains("  char *yytext;\n  IdxType yyleng;\n"));
+        assert!(code.contains("  .g_at_bol = 1,\n"));
+        assert!(code.contains("  .yylineno = 1,\n};\n"));
+        assert!(code.contains("#define yytext (yyg->yytext)\n"));
+        assert!(code.contains("int yywrap(yyscan_t yyscanner);\n"));
+        assert!(code.contains(REENTRANT));
+        assert!(!code.contains(GLOBAL_SCANNER));
+
+        // two scanners taking turns, each keeping its own place and lines
+        let source = r#"%option reentrant noyywrap yylineno
+%%
+[a-z]+      { return 1; }
+.|\n        ;
+%%
+int main(void) {
+  yyscan_t a, b;
+  yylex_init(&a);
+  yylex_init(&b);
+  yy_scan_string("one\ntwo three", a);
+  yy_scan_string("four five", b);
+  while (yylex(a)) {
+    printf("a:%s:%d ", yyget_text(a), yyget_lineno(a));
+    if (yylex(b))
+      printf("b:%s:%d ", yyget_text(b), yyget_lineno(b));
+  }
+  yylex_destroy(a);
+  yylex_destroy(b);
+  return 0;
+}"#;
+        if let Some(output) = run_scanner("reentrant", source, &[], Input::None) {
+            assert_eq!(output, "a:one:1 b:four:1 a:two:2 b:five:1 a:three:2 ");
+        }
+    }
+
+    #[test]
+    fn options() {
+        // later lines add to and override the earlier ones
+        let source = "%option noyywrap nodefault\n%option yywrap 8bit\n%%\n%%";
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        let code = codegen.generate();
+        assert_eq!(
+            codegen.options,
+            vec![
+                LersOption::Yywrap(false),
+                LersOption::Default(false),
+                LersOption::Yywrap(true),
+            ]
+        );
+        assert!(code.contains("int yywrap(void);\n"));
+        assert!(code.contains("      YY_FATAL_ERROR(\"scanner jammed\");\n"));
+        assert!(!code.contains(MAIN));
+
+        let source = "%option main reentrant extra-type=\"int *\"\n%%\n%%";
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(code.contains("#define yywrap(yyscanner) 1\n"));
+        assert!(code.contains("#define YY_EXTRA_TYPE int *\n"));
+        assert!(code.contains(REENTRANT_MAIN));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown option: yywarp")]
+    fn unknown_option() {
+        let source = "%option noyywrap\n%option yywarp\n%%\n%%";
+        CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+    }
+
+    #[test]
+    fn outfile() {
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new("%%\n%%")).parse());
+        codegen.generate();
+        assert_eq!(codegen.outfile(), "lers.yy.c");
+
+        let source = "%option outfile=\"scan.c\"\n%%\n%%";
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        codegen.generate();
+        assert_eq!(codegen.outfile(), "scan.c");
+
+        // -t on the command line wins over the file
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        codegen.add_option(LersOption::Stdout(true));
+        codegen.generate();
+        assert_eq!(codegen.outfile(), "-");
+    }
+
+    #[test]
+    fn header() {
+        let source = "%option prefix=\"calc\" header-file=\"calc.h\"\n%x STR\n%%\n%%";
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        codegen.generate();
+        assert_eq!(codegen.header_file(), Some("calc.h"));
+        let header = codegen.generate_header();
+        assert!(
+            header.starts_with("#ifndef CALC_LEX_H\n#define CALC_LEX_H\n\n#define yylex calclex\n")
+        );
+        assert!(header.contains(GLOBAL_SCANNER));
+        assert!(header.contains("extern char *yytext;\nextern IdxType yyleng;\n\n"));
+        assert!(header.contains(HEADER_BUFFER));
+        assert!(header.contains("int yylex(YY_ONLY_ARG);\nint yywrap(YY_ONLY_ARG);\n"));
+        assert!(header.contains("#undef yylex\n"));
+        assert!(header.contains("#undef yytext\n"));
+        assert!(header.contains("#undef YY_DECL_GUTS_VAR\n#endif\n\n"));
+        assert!(header.ends_with(
+            "#ifdef YY_HEADER_EXPORT_START_CONDITIONS
+#define INITIAL 0
+#define STR 1
+#endif
+
+#endif /* CALC_LEX_H */
+"
+        ));
+
+        let source = "%option reentrant bison-bridge noyywrap\n%%\n%%";
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        codegen.generate();
+        assert_eq!(codegen.header_file(), None);
+        let header = codegen.generate_header();
+        assert!(header.contains(REENTRANT_SCANNER));
+        assert!(!header.contains("extern"));
+        assert!(header.contains(HEADER_REENTRANT));
+        assert!(header.contains("int yylex(YYSTYPE *yylval_param YY_LAST_ARG);\n\n"));
+        assert!(header.contains("#undef YY_EXTRA_TYPE\n"));
+        assert!(!header.contains("#undef yylex\n"));
+    }
+
+    #[test]
+    fn two_headers() {
+        let dir = env::temp_dir().join(format!("lers-two_headers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut files = Vec::new();
+        for (prefix, options) in [("aa", "locations"), ("bb", "reentrant locations")] {
+            let source = format!(
+                "%option noyywrap prefix=\"{}\" header-file=\"{}.h\" {}\n%{{\n#include \"{}.h\"\n%}}\n%%\n[a-z]+  {{ return 1; }}\n.|\\n    ;\n%%\n",
+                prefix, prefix, options, prefix
+            );
+            let mut codegen = CodeGen::new(Parser::new(Lexer::new(&source)).parse());
+            let code = codegen.generate();
+            let c_file = dir.join(format!("{}.c", prefix));
+            fs::write(&c_file, code).unwrap();
+            fs::write(dir.join(format!("{}.h", prefix)), codegen.generate_header()).unwrap();
+            files.push(c_file);
+        }
+        let main = dir.join("main.c");
+        fs::write(
+            &main,
+            r#"#include "aa.h"
+#include "bb.h"
+
+/* the words of the string scanned by both scanners */
+int main(void) {
+  yyscan_t scanner;
+  int words = 0;
+  aa_scan_string("one two");
+  while (aalex())
+    ++words;
+  bblex_init(&scanner);
+  bb_scan_string("three", scanner);
+  while (bblex(scanner))
+    ++words;
+  bblex_destroy(scanner);
+  printf("%d %s %d\n", words, aatext, aalocation.last_column);
+  return 0;
+}
+"#,
+        )
+        .unwrap();
+        files.push(main);
+        let binary = dir.join("scanner");
+        let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let Ok(compiled) = Command::new(compiler)
+            .args(["-Wall", "-Wextra", "-Werror", "-I"])
+            .arg(&dir)
+            .args(&files)
+            .arg("-o")
+            .arg(&binary)
+            .output()
+        else {
+            fs::remove_dir_all(&dir).unwrap();
+            return;
+        };
+        assert!(
+            compiled.status.success(),
+            "{}",
+            String::from_utf8_lossy(&compiled.stderr)
+        );
+        let output = Command::new(&binary).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "3 two 7\n");
+    }
+
+    #[test]
+    fn prefix() {
+        let source = "%option noyywrap prefix=\"calc\"\n%{\nint n;\n%}\n%%\n%%";
+        let code = CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+        assert!(code.starts_with("#define yylex calclex\n#define yyrestart calcrestart\n"));
+        assert!(code.contains("#define yy_scan_string calc_scan_string\n"));
+        assert!(code.contains("#define yytext calctext\n"));
+        assert!(code.contains("#define yylocation calclocation\n\n/*** Definition Code ***/\n"));
+        assert!(!code.contains("#define yywrap calcwrap\n"));
+        assert!(code.contains("static int yy_match(YY_ONLY_ARG) {\n"));
+
+        // the command line wins, variables are fields of a reentrant scanner
+        let source = "%option reentrant prefix=\"calc\"\n%%\n%%";
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        codegen.add_option(LersOption::Prefix("expr".to_string()));
+        let code = codegen.generate();
+        assert!(code.contains("#define yylex_init exprlex_init\n"));
+        assert!(code.contains("#define yywrap exprwrap\n"));
+        assert!(!code.contains("#define yytext exprtext\n"));
+        assert!(!code.contains("calc"));
+
+        let code = CodeGen::new(Parser::new(Lexer::new("%%\n%%")).parse()).generate();
+        assert!(!code.contains("#define yylex "));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid prefix: 9lives")]
+    fn invalid_prefix() {
+        let source = "%option prefix=\"9lives\"\n%%\n%%";
+        CodeGen::new(Parser::new(Lexer::new(source)).parse()).generate();
+    }
+
+    #[test]
+    fn bison_bridge() {
+        let source = r#"
+%option bison-locations
+%%
+[0-9]+      { *yylval = atoi(yytext); yylloc->first_line = 1; return 258; }
+%%"#;
+        let mut codegen = CodeGen::new(Parser::new(Lexer::new(source)).parse());
+        codegen.add_option(LersOption::BisonHeader("calc.tab.h".to_string()));
+        let code = codegen.generate();
+        assert!(code.starts_with("/*** Rule Code ***/\n#include \"calc.tab.h\"\n"));
+        assert!(code.contains(BISON_YYLTYPE));
+        assert!(code.contains(
+            "#define YY_DECL int yylex(YYSTYPE *yylval_param, YYLTYPE *yylloc_param YY_LAST_ARG)\n"
+        ));
+        assert!(code.contains("#define yylval yylval_param\n"));
+        assert!(code.contains("#define yylloc yylloc_param\n"));
+
+        // actions without yylval and yylloc leave the parameters unused
+        let source = r#"%option bison-locations reentrant noyywrap
+%%
+[a-z]+      { return 258; }
+.|\n        ;
+%%
+int main(void) {
+  yyscan_t scanner;
+  YYSTYPE value;
+  YYLTYPE location;
+  int tokens = 0;
+  yylex_init(&scanner);
+  while (yylex(&value, &location, scanner))
+    ++tokens;
+  yylex_destroy(scanner);
+  printf("%d", tokens);
+  return 0;
+}"#;
+        if let Some(output) = run_scanner("bison_bridge", source, &[], Input::Stdin("a b c")) {
+            assert_eq!(output, "3");
+        }
+    }
+
+    fn target_code(tables: &str) -> String {
         format!(
-            "{}{}{}{}{}{}",
+            "{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}",
             r#"/*** Definition Code ***/
     c code block
 /*** Rule Code ***/
 "#,
             PREPARE,
-            r#"#define g_pattern_count 3
-char *g_patterns[] = {
-  "[0-9]+",
-  "[0-9]+",
-  "pattern3",
-  ".",
-};
-
-void action(int pattern_index) {
-  if (pattern_index == 0) {
+            GLOBAL_SCANNER,
+            gen_globals(&[BUFFER_VARIABLES, YYTEXT_POINTER_VARIABLES]),
+            BUFFER,
+            YYTEXT_POINTER,
+            "#define yywrap(yyscanner) 1\n\n#define INITIAL 0\n\n",
+            tables,
+            MATCH,
+            ACTION_HELPERS,
+            "\n#define YY_STATE_EOF(condition) (4 + (condition))\n",
+            YYLEX_START,
+            r#"    case 0:
 { action1(); }
-  }
-  if (pattern_index == 1) {
+      break;
+    case 1:
 { action2(); }
-  }
-  if (pattern_index == 2) {
+      break;
+    case 2:
 { action3(); }
-  }
-  if (pattern_index == 3) { printf("%c", yytext); }
-}
+      break;
+    case 3:
+      ECHO;
+      break;
 "#,
-            REGEX,
-            YYLEX,
+            YYLEX_END,
             r#"
 /*** User Code ***/
 
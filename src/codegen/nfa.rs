@@ -0,0 +1,213 @@
+// build a Non-deterministic Finite Automaton from the rules (Thompson's construction)
+
+use super::regex::{ByteSet, Pattern, Regex};
+
+pub type State = usize;
+
+#[derive(Debug, Default)]
+pub struct NfaState {
+    pub epsilon: Vec<State>,
+    pub edges: Vec<(ByteSet, State)>,
+    /// index of the rule accepted in this state
+    pub accept: Option<usize>,
+    /// the rule is only accepted before a newline or the end of input
+    pub eol: bool,
+    /// the head of the rule with variable trailing context ends in this state
+    pub head_end: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct Nfa {
+    pub states: Vec<NfaState>,
+    /// the start states in the middle of a line and at the beginning of a line,
+    /// for every start condition in turn
+    pub starts: Vec<State>,
+}
+
+impl Nfa {
+    /// combine the rules into one NFA, the start states of every start condition
+    /// have ε-edges to the rules active in it, except that `^` rules are only
+    /// reachable at the beginning of a line
+    pub fn from_rules(rules: &[Pattern], conditions: &[Vec<usize>]) -> Self {
+        let mut nfa = Nfa::default();
+        let mut rule_starts = Vec::new();
+        for (index, pattern) in rules.iter().enumerate() {
+            let (rule_start, mut accept) = nfa.build(&pattern.regex);
+            if let Some(trailing) = &pattern.trailing {
+                // START --head--> HEAD_END --ε--> S₀ --trailing--> END
+                if pattern.has_variable_trailing_context() {
+                    nfa.states[accept].head_end = Some(index);
+                }
+                let (trailing_start, trailing_accept) = nfa.build(trailing);
+                nfa.add_epsilon(accept, trailing_start);
+                accept = trailing_accept;
+            }
+            nfa.states[accept].accept = Some(index);
+            nfa.states[accept].eol = pattern.eol;
+            rule_starts.push(rule_start);
+        }
+        for active in conditions {
+            let start = nfa.new_state();
+            let bol_start = nfa.new_state();
+            nfa.starts.extend([start, bol_start]);
+            for &index in active {
+                if !rules[index].bol {
+                    nfa.add_epsilon(start, rule_starts[index]);
+                }
+                nfa.add_epsilon(bol_start, rule_starts[index]);
+            }
+        }
+        nfa
+    }
+
+    fn new_state(&mut self) -> State {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    fn add_epsilon(&mut self, from: State, to: State) {
+        self.states[from].epsilon.push(to);
+    }
+
+    /// build a fragment for the regex, return its start and accept states
+    fn build(&mut self, regex: &Regex) -> (State, State) {
+        match regex {
+            Regex::Set(set) => {
+                // START --set--> END
+                let start = self.new_state();
+                let accept = self.new_state();
+                self.states[start].edges.push((*set, accept));
+                (start, accept)
+            }
+            Regex::Concat(regexes) => {
+                // START --r1--> S₀ --ε--> S₁ --r2--> ... END
+                let (start, mut accept) = self.build(&regexes[0]);
+                for regex in &regexes[1..] {
+                    let (next_start, next_accept) = self.build(regex);
+                    self.add_epsilon(accept, next_start);
+                    accept = next_accept;
+                }
+                (start, accept)
+            }
+            Regex::Alt(regexes) => {
+                //          /-ε--> S₀ --r1--> S₁ -ε-\
+                // START --<         ...             >--> END
+                //          \-ε--> S₂ --rn--> S₃ -ε-/
+                let start = self.new_state();
+                let accept = self.new_state();
+                for regex in regexes {
+                    let (sub_start, sub_accept) = self.build(regex);
+                    self.add_epsilon(start, sub_start);
+                    self.add_epsilon(sub_accept, accept);
+                }
+                (start, accept)
+            }
+            Regex::Star(regex) => {
+                //               .-<-ε-<-.
+                //              /         \
+                // START --ε--> S₀ --r--> S₁ --ε--> END
+                //     \                            /
+                //      .---------->-ε->-----------.
+                let (start, accept) = self.build_plus(regex);
+                self.add_epsilon(start, accept);
+                (start, accept)
+            }
+            Regex::Plus(regex) => self.build_plus(regex),
+            Regex::Optional(regex) => {
+                // START --r--> END
+                //     \        /
+                //      .--ε-->.
+                let (start, accept) = self.build(regex);
+                self.add_epsilon(start, accept);
+                (start, accept)
+            }
+            Regex::Repeat(regex, min, max) => {
+                // r{2,4} is rrr?r?, and r{2,} is rrr*
+                let mut regexes = vec![regex.as_ref().clone(); *min];
+                match max {
+                    Some(max) => regexes.extend(std::iter::repeat_n(
+                        Regex::Optional(regex.clone()),
+                        max - min,
+                    )),
+                    None => regexes.push(Regex::Star(regex.clone())),
+                }
+                if regexes.is_empty() {
+                    // START --ε--> END
+                    let start = self.new_state();
+                    let accept = self.new_state();
+                    self.add_epsilon(start, accept);
+                    (start, accept)
+                } else {
+                    self.build(&Regex::Concat(regexes))
+                }
+            }
+        }
+    }
+
+    // the same as Star without the ε-edge skipping the body
+    fn build_plus(&mut self, regex: &Regex) -> (State, State) {
+        let start = self.new_state();
+        let (body_start, body_accept) = self.build(regex);
+        let accept = self.new_state();
+        self.add_epsilon(start, body_start);
+        self.add_epsilon(body_accept, body_start);
+        self.add_epsilon(body_accept, accept);
+        (start, accept)
+    }
+
+    /// all states reachable with ε-edges from the given states
+    pub fn epsilon_closure(&self, states: &mut Vec<State>) {
+        let mut visited = vec![false; self.states.len()];
+        for &state in states.iter() {
+            visited[state] = true;
+        }
+        let mut stack = states.clone();
+        while let Some(state) = stack.pop() {
+            for &next in &self.states[state].epsilon {
+                if !visited[next] {
+                    visited[next] = true;
+                    states.push(next);
+                    stack.push(next);
+                }
+            }
+        }
+        states.sort_unstable();
+        states.dedup();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codegen::regex::parse;
+
+    #[test]
+    fn build() {
+        let nfa = Nfa::from_rules(&[parse("ab"), parse("a*"), parse("^b")], &[vec![0, 1, 2]]);
+        let mut states = vec![nfa.starts[0]];
+        nfa.epsilon_closure(&mut states);
+        // the closure reaches the first edge of both rules, and "a*" accepts empty
+        let accepts = states
+            .iter()
+            .filter_map(|&state| nfa.states[state].accept)
+            .collect::<Vec<_>>();
+        assert_eq!(accepts, vec![1]);
+        assert_eq!(
+            states
+                .iter()
+                .filter(|&&state| !nfa.states[state].edges.is_empty())
+                .count(),
+            2
+        );
+
+        let mut bol_states = vec![nfa.starts[1]];
+        nfa.epsilon_closure(&mut bol_states);
+        assert_eq!(
+            bol_states
+                .iter()
+                .filter(|&&state| !nfa.states[state].edges.is_empty())
+                .count(),
+            3
+        );
+    }
+}
@@ -0,0 +1,578 @@
+// parse the regular expression of a rule into an abstract syntax tree
+
+/// a set of bytes, used as the label of an edge
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ByteSet([u64; 4]);
+
+impl ByteSet {
+    pub fn new() -> Self {
+        ByteSet::default()
+    }
+
+    pub fn single(byte: u8) -> Self {
+        let mut set = ByteSet::new();
+        set.insert(byte);
+        set
+    }
+
+    /// anything but newline, the meaning of `.`
+    pub fn dot() -> Self {
+        ByteSet::single(b'\n').negate()
+    }
+
+    pub fn insert(&mut self, byte: u8) {
+        self.0[(byte >> 6) as usize] |= 1 << (byte & 63);
+    }
+
+    pub fn contains(&self, byte: u8) -> bool {
+        self.0[(byte >> 6) as usize] & (1 << (byte & 63)) != 0
+    }
+
+    pub fn negate(self) -> Self {
+        ByteSet(self.0.map(|bits| !bits))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=255u8).filter(|&byte| self.contains(byte))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Regex {
+    Set(ByteSet),
+    Concat(Vec<Regex>),
+    Alt(Vec<Regex>),
+    Star(Box<Regex>),
+    Plus(Box<Regex>),
+    Optional(Box<Regex>),
+    /// at least min and at most max times, no upper bound if max is None
+    Repeat(Box<Regex>, usize, Option<usize>),
+}
+
+impl Regex {
+    pub fn literal(byte: u8) -> Self {
+        Regex::Set(ByteSet::single(byte))
+    }
+
+    /// the length of every text matched, if they are the same
+    pub fn fixed_length(&self) -> Option<usize> {
+        match self {
+            Regex::Set(_) => Some(1),
+            Regex::Concat(regexes) => regexes.iter().map(Regex::fixed_length).sum(),
+            Regex::Alt(regexes) => {
+                let len = regexes[0].fixed_length()?;
+                regexes[1..]
+                    .iter()
+                    .all(|regex| regex.fixed_length() == Some(len))
+                    .then_some(len)
+            }
+            Regex::Star(_) | Regex::Plus(_) | Regex::Optional(_) => None,
+            Regex::Repeat(regex, min, max) => {
+                if max == &Some(*min) {
+                    regex.fixed_length().map(|len| len * min)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// whether any text matched may contain the byte
+    pub fn may_contain(&self, byte: u8) -> bool {
+        match self {
+            Regex::Set(set) => set.contains(byte),
+            Regex::Concat(regexes) | Regex::Alt(regexes) => {
+                regexes.iter().any(|regex| regex.may_contain(byte))
+            }
+            Regex::Star(regex)
+            | Regex::Plus(regex)
+            | Regex::Optional(regex)
+            | Regex::Repeat(regex, _, _) => regex.may_contain(byte),
+        }
+    }
+}
+
+/// the pattern of a rule, a regex with its anchors and trailing context
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub regex: Regex,
+    /// `r/s`, s must follow but is not part of the match
+    pub trailing: Option<Regex>,
+    /// `^`, only match at the beginning of a line
+    pub bol: bool,
+    /// `$`, only match before a newline or the end of input
+    pub eol: bool,
+}
+
+impl Pattern {
+    /// neither the head nor the trailing context has a fixed length, the
+    /// scanner has to find where the head ends at run time
+    pub fn has_variable_trailing_context(&self) -> bool {
+        self.trailing.as_ref().is_some_and(|trailing| {
+            self.regex.fixed_length().is_none() && trailing.fixed_length().is_none()
+        })
+    }
+}
+
+/// parse a pattern, panic with a readable message if it is malformed
+pub fn parse(pattern: &str) -> Pattern {
+    let mut parser = RegexParser {
+        pattern,
+        bytes: pattern.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    // anchors are only special at the beginning and the end of a pattern
+    let bol = pattern.len() > 1 && parser.peek() == Some(b'^');
+    if bol {
+        parser.pos += 1;
+    }
+    let regex = parser.parse_expr();
+    let trailing = (parser.peek() == Some(b'/')).then(|| {
+        parser.pos += 1;
+        parser.parse_expr()
+    });
+    let eol = parser.at_eol_anchor();
+    if eol {
+        parser.pos += 1;
+    }
+    if parser.peek() == Some(b'/') {
+        parser.error("trailing context used twice");
+    }
+    if parser.pos < parser.bytes.len() {
+        parser.error("unexpected trailing character");
+    }
+    Pattern {
+        regex,
+        trailing,
+        bol,
+        eol,
+    }
+}
+
+struct RegexParser<'a> {
+    pattern: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    /// how many parentheses the parser is in
+    depth: usize,
+}
+
+impl RegexParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        self.pos += 1;
+        byte
+    }
+
+    // a '$' ending a pattern which is not only '$'
+    fn at_eol_anchor(&self) -> bool {
+        self.pos > 0 && self.pos + 1 == self.bytes.len() && self.peek() == Some(b'$')
+    }
+
+    fn eat(&mut self, expected: u8) {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+        } else {
+            self.error(&format!("expected '{}'", expected as char));
+        }
+    }
+
+    fn error(&self, message: &str) -> ! {
+        panic!(
+            "{} at position {} in pattern \"{}\"",
+            message, self.pos, self.pattern
+        );
+    }
+
+    /// expr := term ('|' term)*
+    fn parse_expr(&mut self) -> Regex {
+        let mut alternatives = vec![self.parse_term()];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            alternatives.push(self.parse_term());
+        }
+        if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Regex::Alt(alternatives)
+        }
+    }
+
+    /// term := factor+
+    fn parse_term(&mut self) -> Regex {
+        let mut factors = Vec::new();
+        while let Some(byte) = self.peek() {
+            if byte == b'|' || byte == b')' || self.at_eol_anchor() {
+                break;
+            }
+            // trailing context is only allowed outside parentheses
+            if byte == b'/' && self.depth == 0 {
+                break;
+            }
+            factors.push(self.parse_factor());
+        }
+        match factors.len() {
+            0 => self.error("empty expression"),
+            1 => factors.pop().unwrap(),
+            _ => Regex::Concat(factors),
+        }
+    }
+
+    /// factor := base ('*' | '+' | '?' | '{' count '}')*
+    fn parse_factor(&mut self) -> Regex {
+        let mut node = self.parse_base();
+        loop {
+            match self.peek() {
+                Some(b'*') => node = Regex::Star(Box::new(node)),
+                Some(b'+') => node = Regex::Plus(Box::new(node)),
+                Some(b'?') => node = Regex::Optional(Box::new(node)),
+                Some(b'{') => {
+                    let (min, max) = self.parse_count();
+                    node = Regex::Repeat(Box::new(node), min, max);
+                    continue;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        node
+    }
+
+    /// count := NUMBER | NUMBER ',' | NUMBER ',' NUMBER
+    fn parse_count(&mut self) -> (usize, Option<usize>) {
+        self.eat(b'{');
+        if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.pos -= 1;
+            self.error_brace();
+        }
+        let min = self.parse_decimal();
+        let max = if self.peek() == Some(b',') {
+            self.pos += 1;
+            if self.peek() == Some(b'}') {
+                None
+            } else {
+                Some(self.parse_decimal())
+            }
+        } else {
+            Some(min)
+        };
+        self.eat(b'}');
+        if max.is_some_and(|max| max < min) {
+            self.error("invalid repetition count");
+        }
+        (min, max)
+    }
+
+    fn parse_decimal(&mut self) -> usize {
+        let start = self.pos;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        match self.pattern[start..self.pos].parse() {
+            Ok(value) => value,
+            Err(_) => self.error("expected a number"),
+        }
+    }
+
+    // a '{' at pos starting neither a count nor a known definition
+    fn error_brace(&self) -> ! {
+        let rest = &self.bytes[self.pos + 1..];
+        let name_len = rest
+            .iter()
+            .take_while(|byte| byte.is_ascii_alphanumeric() || **byte == b'_')
+            .count();
+        if name_len > 0 && rest.get(name_len) == Some(&b'}') {
+            let name = &self.pattern[self.pos + 1..self.pos + 1 + name_len];
+            self.error(&format!("undefined definition {{{}}}", name));
+        }
+        self.error("expected a repetition count after '{'")
+    }
+
+    /// base := LITERAL
+    ///       | '{' NAME '}' (only when undefined, or it was expanded by the parser)
+    ///       | '\' any_single_character
+    ///       | '.'
+    ///       | '[' range ']'
+    ///       | '(' expr ')'
+    ///       | '"' any_character_but_quote* '"'
+    fn parse_base(&mut self) -> Regex {
+        match self.bump() {
+            Some(b'\\') => Regex::literal(self.parse_escape()),
+            Some(b'.') => Regex::Set(ByteSet::dot()),
+            Some(b'[') => {
+                let set = self.parse_range();
+                self.eat(b']');
+                Regex::Set(set)
+            }
+            Some(b'(') => {
+                self.depth += 1;
+                let node = self.parse_expr();
+                self.eat(b')');
+                self.depth -= 1;
+                node
+            }
+            Some(b'/') => {
+                self.pos -= 1;
+                self.error("trailing context inside parentheses")
+            }
+            Some(b'"') => self.parse_quoted(),
+            Some(b'{') => {
+                self.pos -= 1;
+                self.error_brace()
+            }
+            Some(b'*') | Some(b'+') | Some(b'?') => {
+                self.pos -= 1;
+                self.error("nothing to repeat")
+            }
+            Some(byte) => Regex::literal(byte),
+            None => self.error("unexpected end of pattern"),
+        }
+    }
+
+    /// every character in quotes matches itself, only backslash escapes work
+    fn parse_quoted(&mut self) -> Regex {
+        let mut literals = Vec::new();
+        loop {
+            match self.bump() {
+                Some(b'"') => break,
+                Some(b'\\') => literals.push(Regex::literal(self.parse_escape())),
+                Some(byte) => literals.push(Regex::literal(byte)),
+                None => self.error("unterminated quoted string"),
+            }
+        }
+        match literals.len() {
+            0 => self.error("empty quoted string"),
+            1 => literals.pop().unwrap(),
+            _ => Regex::Concat(literals),
+        }
+    }
+
+    /// the character after a backslash
+    fn parse_escape(&mut self) -> u8 {
+        match self.bump() {
+            Some(b'a') => 0x07,
+            Some(b'b') => 0x08,
+            Some(b'f') => 0x0c,
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'v') => 0x0b,
+            Some(b'x') => self.parse_number(16, 2),
+            Some(b'0'..=b'7') => {
+                self.pos -= 1;
+                self.parse_number(8, 3)
+            }
+            Some(byte) => byte,
+            None => self.error("trailing backslash"),
+        }
+    }
+
+    fn parse_number(&mut self, radix: u32, max_digits: usize) -> u8 {
+        let start = self.pos;
+        while self.pos - start < max_digits
+            && self
+                .peek()
+                .is_some_and(|byte| (byte as char).is_digit(radix))
+        {
+            self.pos += 1;
+        }
+        let digits = &self.pattern[start..self.pos];
+        match u8::from_str_radix(digits, radix) {
+            Ok(value) => value,
+            Err(_) => self.error("invalid numeric escape"),
+        }
+    }
+
+    /// range := '^'? (LITERAL | '\' any_single_character | LITERAL '-' LITERAL)+
+    fn parse_range(&mut self) -> ByteSet {
+        let mut set = ByteSet::new();
+        let is_neg = self.peek() == Some(b'^');
+        if is_neg {
+            self.pos += 1;
+        }
+
+        // a leading ']' is a member of the set
+        let mut first = true;
+        loop {
+            let from = match self.bump() {
+                Some(b']') if !first => {
+                    self.pos -= 1;
+                    break;
+                }
+                Some(b'\\') => self.parse_escape(),
+                Some(byte) => byte,
+                None => self.error("unterminated character class"),
+            };
+            first = false;
+            // a trailing '-' is a member of the set
+            if self.peek() == Some(b'-') && self.bytes.get(self.pos + 1) != Some(&b']') {
+                self.pos += 1;
+                let to = match self.bump() {
+                    Some(b'\\') => self.parse_escape(),
+                    Some(byte) => byte,
+                    None => self.error("unterminated character class"),
+                };
+                if from > to {
+                    self.error("invalid range in character class");
+                }
+                for byte in from..=to {
+                    set.insert(byte);
+                }
+            } else {
+                set.insert(from);
+            }
+        }
+
+        if is_neg { set.negate() } else { set }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_regex(pattern: &str) -> Regex {
+        parse(pattern).regex
+    }
+
+    fn byte_set(bytes: &[u8]) -> ByteSet {
+        let mut set = ByteSet::new();
+        for &byte in bytes {
+            set.insert(byte);
+        }
+        set
+    }
+
+    fn set(bytes: &[u8]) -> Regex {
+        Regex::Set(byte_set(bytes))
+    }
+
+    #[test]
+    fn parse_pattern() {
+        assert_eq!(
+            parse_regex("fo*|[0-2]+"),
+            Regex::Alt(vec![
+                Regex::Concat(vec![
+                    Regex::literal(b'f'),
+                    Regex::Star(Box::new(Regex::literal(b'o'))),
+                ]),
+                Regex::Plus(Box::new(set(b"012"))),
+            ])
+        );
+        assert_eq!(
+            parse_regex(r"(\n|\+)\."),
+            Regex::Concat(vec![
+                Regex::Alt(vec![Regex::literal(b'\n'), Regex::literal(b'+')]),
+                Regex::literal(b'.'),
+            ])
+        );
+        assert_eq!(
+            parse_regex("[^ \\t\\n]"),
+            Regex::Set(byte_set(b" \t\n").negate())
+        );
+        assert_eq!(parse_regex("[]a-]"), set(b"]a-"));
+        assert_eq!(
+            parse_regex(r"\x41\101"),
+            Regex::Concat(vec![set(b"A"), set(b"A")])
+        );
+    }
+
+    #[test]
+    fn parse_anchors() {
+        let pattern = parse("^#include$");
+        assert!(pattern.bol && pattern.eol);
+        assert_eq!(pattern.regex, parse_regex("#include"));
+        let pattern = parse("a|b$");
+        assert!(!pattern.bol && pattern.eol);
+        assert_eq!(pattern.regex, parse_regex("a|b"));
+        // anchors elsewhere are literals
+        let pattern = parse("a^b$c");
+        assert!(!pattern.bol && !pattern.eol);
+        assert_eq!(parse_regex("$"), Regex::literal(b'$'));
+        assert_eq!(parse_regex("^"), Regex::literal(b'^'));
+        assert_eq!(parse_regex("(a$)"), parse_regex(r"a\$"));
+    }
+
+    #[test]
+    fn parse_trailing_context() {
+        let pattern = parse("ab/c+$");
+        assert_eq!(pattern.regex, parse_regex("ab"));
+        assert_eq!(pattern.trailing, Some(parse_regex("c+")));
+        assert!(pattern.eol);
+        assert!(!pattern.has_variable_trailing_context());
+        assert!(parse("a+/(b|cd)").has_variable_trailing_context());
+        assert!(!parse("a+/(b|c){2}").has_variable_trailing_context());
+        assert_eq!(parse(r#"\/"/""#).trailing, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "trailing context used twice")]
+    fn double_trailing_context() {
+        parse("a/b/c");
+    }
+
+    #[test]
+    fn parse_quoted() {
+        let literals =
+            |bytes: &[u8]| Regex::Concat(bytes.iter().map(|&b| Regex::literal(b)).collect());
+        assert_eq!(parse_regex(r#""/*""#), literals(b"/*"));
+        assert_eq!(parse_regex(r#""a|b\"\n""#), literals(b"a|b\"\n"));
+        assert_eq!(
+            parse_regex(r#""+="+"#),
+            Regex::Plus(Box::new(literals(b"+=")))
+        );
+        assert_eq!(
+            parse_regex(r#"x"{1}"{1}"#),
+            Regex::Concat(vec![
+                Regex::literal(b'x'),
+                Regex::Repeat(Box::new(literals(b"{1}")), 1, Some(1)),
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "unterminated quoted string")]
+    fn unterminated_quote() {
+        parse_regex(r#""abc"#);
+    }
+
+    #[test]
+    fn parse_repetition() {
+        let digit = || Box::new(set(b"0123456789"));
+        assert_eq!(
+            parse_regex("-?[0-9]{4}"),
+            Regex::Concat(vec![
+                Regex::Optional(Box::new(Regex::literal(b'-'))),
+                Regex::Repeat(digit(), 4, Some(4)),
+            ])
+        );
+        assert_eq!(parse_regex("[0-9]{2,}"), Regex::Repeat(digit(), 2, None));
+        assert_eq!(
+            parse_regex("[0-9]{2,4}?"),
+            Regex::Optional(Box::new(Regex::Repeat(digit(), 2, Some(4))))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "undefined definition {digit}")]
+    fn undefined_definition() {
+        parse_regex("{digit}+");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid repetition count")]
+    fn invalid_count() {
+        parse_regex("a{4,2}");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected ')'")]
+    fn unbalanced_paren() {
+        parse_regex("(ab");
+    }
+}
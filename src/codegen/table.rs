@@ -0,0 +1,136 @@
+// lay out the transitions of a DFA in the compressed base/next/check form
+
+use super::dfa::{DEAD, Dfa};
+
+/// transitions indexed by equivalence class, looked up like:
+///
+/// ```c
+/// while (state != DEAD && check[base[state] + class] != state)
+///   state = default[state];
+/// next_state = state == DEAD ? DEAD : next[base[state] + class];
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct CompressedTable {
+    pub base: Vec<usize>,
+    pub default: Vec<usize>,
+    pub next: Vec<usize>,
+    pub check: Vec<usize>,
+}
+
+impl CompressedTable {
+    pub fn new(dfa: &Dfa, classes: &[usize]) -> Self {
+        let class_count = classes.iter().max().map_or(0, |&class| class + 1);
+        // one byte standing for every equivalence class
+        let mut symbols = Vec::new();
+        for (byte, &class) in classes.iter().enumerate() {
+            if class == symbols.len() {
+                symbols.push(byte);
+            }
+        }
+        let rows = dfa
+            .states
+            .iter()
+            .map(|state| {
+                symbols
+                    .iter()
+                    .map(|&byte| state.next[byte])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut table = CompressedTable {
+            base: vec![0; rows.len()],
+            default: vec![DEAD; rows.len()],
+            ..Default::default()
+        };
+        // the dead state stores nothing, every lookup ends there
+        for (state, row) in rows.iter().enumerate().skip(1) {
+            // take the state differing the least from this one as the default,
+            // and only store the differences
+            let mut default = DEAD;
+            let mut differences = row.iter().filter(|&&next| next != DEAD).count();
+            for (other, other_row) in rows.iter().enumerate().take(state).skip(1) {
+                let count = row.iter().zip(other_row).filter(|(a, b)| a != b).count();
+                if count < differences {
+                    default = other;
+                    differences = count;
+                }
+            }
+            let entries = (0..class_count)
+                .filter(|&class| row[class] != rows[default][class])
+                .collect::<Vec<_>>();
+
+            // the first base where all entries fit into free slots
+            let base = (0..)
+                .find(|&base| {
+                    entries.iter().all(|&class| {
+                        table
+                            .check
+                            .get(base + class)
+                            .is_none_or(|&check| check == DEAD)
+                    })
+                })
+                .unwrap();
+            if table.next.len() < base + class_count {
+                table.next.resize(base + class_count, DEAD);
+                table.check.resize(base + class_count, DEAD);
+            }
+            for class in entries {
+                table.next[base + class] = row[class];
+                table.check[base + class] = state;
+            }
+            table.base[state] = base;
+            table.default[state] = default;
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codegen::nfa::Nfa;
+    use crate::codegen::regex::parse;
+
+    impl CompressedTable {
+        // the same as yy_next_state in the generated scanner
+        fn lookup(&self, mut state: usize, class: usize) -> usize {
+            while state != DEAD && self.check[self.base[state] + class] != state {
+                state = self.default[state];
+            }
+            if state == DEAD {
+                DEAD
+            } else {
+                self.next[self.base[state] + class]
+            }
+        }
+    }
+
+    #[test]
+    fn compress() {
+        let rules = [
+            "BEGIN",
+            "END",
+            ":=",
+            "[0-9]+",
+            "[A-Za-z]+",
+            "[ \\t\\n]+",
+            ".|\\n",
+        ]
+        .map(parse);
+        let dfa = Dfa::from_nfa(
+            &Nfa::from_rules(&rules, &[(0..rules.len()).collect()]),
+            false,
+        )
+        .minimize();
+        let classes = dfa.equivalence_classes();
+        let table = CompressedTable::new(&dfa, &classes);
+        for (index, state) in dfa.states.iter().enumerate() {
+            for (&class, &next) in classes.iter().zip(&state.next) {
+                assert_eq!(table.lookup(index, class), next);
+            }
+        }
+        let class_count = classes.iter().max().unwrap() + 1;
+        assert!(table.next.len() < dfa.states.len() * class_count / 2);
+    }
+}
@@ -0,0 +1,161 @@
+// the variables of the runtime, declared as globals or, in a reentrant scanner,
+// as the fields of struct yyguts_t with macros naming them like the globals
+
+/// a variable of the runtime, the internal ones start with `g_` and are static
+#[derive(Debug, PartialEq)]
+pub struct Variable {
+    /// written above the variable, for the group it starts
+    pub comment: Option<&'static str>,
+    /// `FILE *` or `int`, a pointer type ends with `*`
+    pub ty: &'static str,
+    pub name: &'static str,
+    /// the size of an array
+    pub size: Option<&'static str>,
+    /// the initial value, zero when there is none
+    pub value: Option<&'static str>,
+}
+
+impl Variable {
+    pub const fn new(ty: &'static str, name: &'static str) -> Self {
+        Variable {
+            comment: None,
+            ty,
+            name,
+            size: None,
+            value: None,
+        }
+    }
+
+    pub const fn comment(mut self, comment: &'static str) -> Self {
+        self.comment = Some(comment);
+        self
+    }
+
+    pub const fn size(mut self, size: &'static str) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub const fn value(mut self, value: &'static str) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    // `char yytext[YYLMAX]`
+    fn declaration(&self) -> String {
+        let space = if self.ty.ends_with('*') { "" } else { " " };
+        match self.size {
+            Some(size) => format!("{}{}{}[{}]", self.ty, space, self.name, size),
+            None => format!("{}{}{}", self.ty, space, self.name),
+        }
+    }
+}
+
+/// the variables as globals, a blank line after every group and before every
+/// comment within one
+pub fn gen_globals(groups: &[&[Variable]]) -> String {
+    let mut code = String::new();
+    for group in groups {
+        for (i, variable) in group.iter().enumerate() {
+            if let Some(comment) = variable.comment {
+                if i > 0 {
+                    code.push('\n');
+                }
+                code.push_str(&format!("/* {} */\n", comment));
+            }
+            if variable.name.starts_with("g_") {
+                code.push_str("static ");
+            }
+            code.push_str(&variable.declaration());
+            if let Some(value) = variable.value {
+                code.push_str(&format!(" = {}", value));
+            }
+            code.push_str(";\n");
+        }
+        code.push('\n');
+    }
+    code
+}
+
+/// struct yyguts_t holding the variables, the initial state of a new scanner
+/// and the macros naming the fields
+pub fn gen_guts(groups: &[&[Variable]]) -> String {
+    let mut fields = String::new();
+    let mut initial = String::new();
+    let mut macros = String::new();
+    for variable in groups.iter().flat_map(|group| group.iter()) {
+        if let Some(comment) = variable.comment {
+            fields.push_str(&format!("  /* {} */\n", comment));
+        }
+        fields.push_str(&format!("  {};\n", variable.declaration()));
+        if let Some(value) = variable.value {
+            initial.push_str(&format!("  .{} = {},\n", variable.name, value));
+        }
+        macros.push_str(&format!(
+            "#define {} (yyg->{})\n",
+            variable.name, variable.name
+        ));
+    }
+    format!(
+        "/* the state of a scanner */\nstruct yyguts_t {{\n{}}};\n\n\
+         /* a new scanner starts from here */\n\
+         static const struct yyguts_t yy_initial_guts = {{\n{}}};\n\n{}\n",
+        fields, initial, macros
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VARIABLES: &[Variable] = &[
+        Variable::new("FILE *", "yyin")
+            .value("NULL")
+            .comment("the input"),
+        Variable::new("char", "yytext").size("YYLMAX"),
+        Variable::new("int", "g_eof").comment("the end of the input"),
+    ];
+
+    #[test]
+    fn globals() {
+        assert_eq!(
+            gen_globals(&[VARIABLES, &[Variable::new("int", "yylineno").value("1")]]),
+            "/* the input */
+FILE *yyin = NULL;
+char yytext[YYLMAX];
+
+/* the end of the input */
+static int g_eof;
+
+int yylineno = 1;
+
+"
+        );
+    }
+
+    #[test]
+    fn guts() {
+        assert_eq!(
+            gen_guts(&[VARIABLES]),
+            "/* the state of a scanner */
+struct yyguts_t {
+  /* the input */
+  FILE *yyin;
+  char yytext[YYLMAX];
+  /* the end of the input */
+  int g_eof;
+};
+
+/* a new scanner starts from here */
+static const struct yyguts_t yy_initial_guts = {
+  .yyin = NULL,
+};
+
+#define yyin (yyg->yyin)
+#define yytext (yyg->yytext)
+#define g_eof (yyg->g_eof)
+
+"
+        );
+    }
+}
@@ -8,6 +8,18 @@ pub enum DefinitionToken<'a> {
     #[token("%option")]
     OptionStart,
 
+    #[token("%s")]
+    InclusiveStart,
+
+    #[token("%x")]
+    ExclusiveStart,
+
+    #[token("%array")]
+    Array,
+
+    #[token("%pointer")]
+    Pointer,
+
     #[regex(r"[A-Za-z]+", |lex| lex.slice())]
     Identifier(&'a str),
 
@@ -18,7 +30,7 @@ pub enum DefinitionToken<'a> {
     #[regex(r"\n[A-Za-z_][A-Za-z0-9_]*", |lex| &lex.slice()[1..])] // skip the leading newline
     Name(&'a str),
 
-    #[regex(r"([^\s\[]|\[[^\]]+\])+", |lex| lex.slice(), priority = 1)]
+    #[regex(r#"([^\s\["\\]|\\[^\n]|\[[^\]]+\]|"([^"\\\n]|\\[^\n])*")+"#, |lex| lex.slice(), priority = 1)]
     Pattern(&'a str),
 
     #[token("\n")]
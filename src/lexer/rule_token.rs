@@ -1,20 +1,94 @@
 use logos::Logos;
 
 #[derive(Logos, Debug, PartialEq)]
-#[logos(skip r"[ ]+")] // Ignore this regex pattern between tokens
+#[logos(skip r"[ \t]+")] // Ignore this regex pattern between tokens
 #[logos(skip r"/\*.*?\*/")] // Ignore comments
 pub enum RuleToken<'a> {
-    #[regex(r"\n([^\s\[]|\[[^\]]+\])+", |lex| &lex.slice()[1..])]
-    // any non-blank characters from start of a line
+    // any non-blank characters from start of a line, blanks can be escaped or quoted,
+    // a pattern never starts with `/` like a comment
+    #[regex(r#"\n([^\s\["\\/]|\\[^\n]|\[[^\]]+\]|"([^"\\\n]|\\[^\n])*")([^\s\["\\]|\\[^\n]|\[[^\]]+\]|"([^"\\\n]|\\[^\n])*")*"#, |lex| &lex.slice()[1..])]
     Pattern(&'a str),
 
-    #[regex(r"(?s)\{[^\}]*}", |lex| lex.slice())] // anything surrounded by bracket
+    // the same indented, only a pattern inside a `<SC>{ }` scope, as indented
+    // lines are C code elsewhere
+    #[regex(r#"\n[ \t]+([^\s\["\\/]|\\[^\n]|\[[^\]]+\]|"([^"\\\n]|\\[^\n])*")([^\s\["\\]|\\[^\n]|\[[^\]]+\]|"([^"\\\n]|\\[^\n])*")*"#, |lex| trim_indent(lex.slice()))]
+    IndentedPattern(&'a str),
+
+    // a pattern prefixed by its start conditions, like `<STRING,COMMENT>\n`
+    #[regex(r#"\n[ \t]*<(\*|[A-Za-z_][A-Za-z0-9_]*(,[A-Za-z_][A-Za-z0-9_]*)*)>([^\s\["\\]|\\[^\n]|\[[^\]]+\]|"([^"\\\n]|\\[^\n])*")+"#, |lex| split_conditions(trim_indent(lex.slice())), priority = 100)]
+    ConditionPattern((&'a str, &'a str)),
+
+    // `<STRING>{` applies the start conditions to the rules until the matching `}`
+    #[regex(r"\n[ \t]*<(\*|[A-Za-z_][A-Za-z0-9_]*(,[A-Za-z_][A-Za-z0-9_]*)*)>\{[ \t]*", |lex| split_conditions(trim_indent(lex.slice())).0, priority = 200)]
+    ScopeStart(&'a str),
+
+    #[regex(r"\n[ \t]*\}", priority = 200)]
+    ScopeEnd,
+
+    // C code surrounded by braces, or the rest of the line, which doesn't start
+    // with `/` so a comment after the pattern is skipped
+    #[token("{", braced_action)]
+    #[regex(r"[^ \t\n{|/][^\n]*", |lex| lex.slice().trim_end(), allow_greedy = true)]
     Action(&'a str),
 
+    // `|` instead of an action, the rule has the same action as the next one
+    #[token("|")]
+    NextAction,
+
     #[token("\n")]
     Newline,
 }
 
+// skip the leading newline and indentation
+fn trim_indent(slice: &str) -> &str {
+    slice[1..].trim_start_matches([' ', '\t'])
+}
+
+// the action ends at the matching `}`, braces in string and character literals
+// and comments don't count
+fn braced_action<'a>(lex: &mut logos::Lexer<'a, RuleToken<'a>>) -> &'a str {
+    let rest = lex.remainder().as_bytes();
+    let mut depth = 1;
+    let mut i = 0;
+    while depth > 0 {
+        match rest.get(i) {
+            None => panic!("Unmatched '{{' in action"),
+            Some(b'{') => depth += 1,
+            Some(b'}') => depth -= 1,
+            Some(&quote @ (b'"' | b'\'')) => {
+                i += 1;
+                while rest.get(i).is_some_and(|&c| c != quote && c != b'\n') {
+                    if rest[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            Some(b'/') if rest.get(i + 1) == Some(&b'/') => {
+                while rest.get(i + 1).is_some_and(|&c| c != b'\n') {
+                    i += 1;
+                }
+            }
+            Some(b'/') if rest.get(i + 1) == Some(&b'*') => {
+                let Some(end) = lex.remainder()[i + 2..].find("*/") else {
+                    panic!("Unterminated comment in action");
+                };
+                i += end + 3;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    lex.bump(i);
+    lex.slice()
+}
+
+// split `<A,B>pattern` into `A,B` and `pattern`
+fn split_conditions(slice: &str) -> (&str, &str) {
+    let end = slice.find('>').unwrap();
+    (&slice[1..end], &slice[end + 1..])
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -48,4 +122,94 @@ mod test {
         token_eq!(lex, RuleToken::Pattern(".|\\n"));
         token_match!(lex, RuleToken::Action(_));
     }
+
+    #[test]
+    fn quoted_pattern() {
+        let rules = r#"
+"+="        { add(); }
+"/*"        { comment(); }
+"a \" b"*   { quote(); }
+\ +         { space(); }"#;
+        let mut lex = RuleToken::lexer(rules);
+
+        token_eq!(lex, RuleToken::Pattern("\"+=\""));
+        token_eq!(lex, RuleToken::Action("{ add(); }"));
+        token_eq!(lex, RuleToken::Pattern("\"/*\""));
+        token_eq!(lex, RuleToken::Action("{ comment(); }"));
+        token_eq!(lex, RuleToken::Pattern(r#""a \" b"*"#));
+        token_eq!(lex, RuleToken::Action("{ quote(); }"));
+        token_eq!(lex, RuleToken::Pattern("\\ +"));
+        token_eq!(lex, RuleToken::Action("{ space(); }"));
+        assert_eq!(lex.next(), None);
+    }
+
+    #[test]
+    fn actions() {
+        let rules = r#"
+[0-9]+	printf("num\n");
+"{"     { if (x) { y("}"); } else { z('}', "\"}"); } }
+"/*"    {
+            // a comment with }
+            /* and another one with } */
+            skip_comment();
+        }
+ab|cd   ECHO; /* not a comment */
+"#;
+        let mut lex = RuleToken::lexer(rules);
+
+        token_eq!(lex, RuleToken::Pattern("[0-9]+"));
+        token_eq!(lex, RuleToken::Action("printf(\"num\\n\");"));
+        token_eq!(lex, RuleToken::Pattern("\"{\""));
+        token_eq!(
+            lex,
+            RuleToken::Action(r#"{ if (x) { y("}"); } else { z('}', "\"}"); } }"#)
+        );
+        token_eq!(lex, RuleToken::Pattern("\"/*\""));
+        let Some(Ok(RuleToken::Action(action))) = lex.next() else {
+            panic!("Expected action");
+        };
+        assert!(action.ends_with("skip_comment();\n        }"));
+        token_eq!(lex, RuleToken::Pattern("ab|cd"));
+        token_eq!(lex, RuleToken::Action("ECHO; /* not a comment */"));
+        token_eq!(lex, RuleToken::Newline);
+        assert_eq!(lex.next(), None);
+    }
+
+    #[test]
+    fn start_conditions() {
+        let rules = r#"
+<STRING>\"      { end(); }
+<A,B>"<"        { less(); }
+<*>.            { any(); }
+"<>"            |
+<=              { less_equal(); }
+<<EOF>>         { end(); }
+<COMMENT>{
+    "*/"        { leave(); }
+    <<EOF>>     { unterminated(); }
+    /* comment */
+}"#;
+        let mut lex = RuleToken::lexer(rules);
+
+        token_eq!(lex, RuleToken::ConditionPattern(("STRING", "\\\"")));
+        token_eq!(lex, RuleToken::Action("{ end(); }"));
+        token_eq!(lex, RuleToken::ConditionPattern(("A,B", "\"<\"")));
+        token_eq!(lex, RuleToken::Action("{ less(); }"));
+        token_eq!(lex, RuleToken::ConditionPattern(("*", ".")));
+        token_eq!(lex, RuleToken::Action("{ any(); }"));
+        token_eq!(lex, RuleToken::Pattern("\"<>\""));
+        token_eq!(lex, RuleToken::NextAction);
+        token_eq!(lex, RuleToken::Pattern("<="));
+        token_eq!(lex, RuleToken::Action("{ less_equal(); }"));
+        token_eq!(lex, RuleToken::Pattern("<<EOF>>"));
+        token_eq!(lex, RuleToken::Action("{ end(); }"));
+        token_eq!(lex, RuleToken::ScopeStart("COMMENT"));
+        token_eq!(lex, RuleToken::IndentedPattern("\"*/\""));
+        token_eq!(lex, RuleToken::Action("{ leave(); }"));
+        token_eq!(lex, RuleToken::IndentedPattern("<<EOF>>"));
+        token_eq!(lex, RuleToken::Action("{ unterminated(); }"));
+        token_eq!(lex, RuleToken::Newline);
+        token_eq!(lex, RuleToken::ScopeEnd);
+        assert_eq!(lex.next(), None);
+    }
 }
@@ -3,26 +3,107 @@ mod codegen;
 mod lexer;
 mod parser;
 
-use codegen::CodeGen;
+use codegen::{CodeGen, LersOption};
 use lexer::Lexer;
 use parser::Parser;
-use std::{env, fs};
+use std::io::{self, Write};
+use std::{env, fs, process};
+
+const USAGE: &str = "Usage: lers [options] <source-file>
+  <source-file> is read from the standard input when it is -
+  -o <file>, -o<file>, --outfile=<file>
+         write the scanner to <file> instead of lers.yy.c
+  -t, --stdout
+         write the scanner to the standard output
+  -Cf    generate full tables, fast but large
+  -Ce    generate compressed tables, small but slower (default)
+  -I, --interactive
+         stop reading after a token that cannot go on (default)
+  -B, --batch
+         read ahead after every token, for input which is not typed
+  --always-interactive, --never-interactive
+         read every input a line at a time, or in blocks, without asking isatty
+  -P<prefix>, --prefix=<prefix>
+         use <prefix> instead of yy in the exported symbols
+  --header-file[=<file>]
+         also write the declarations of the scanner to <file>, lers.yy.h by default
+  --bison-header=<file>
+         include the token header generated by bison";
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if let Some(filename) = args.get(1) {
-        read_and_parse_and_write(filename);
-    } else {
-        println!("Usage: lers <source-file>");
+    let mut filename = None;
+    let mut options = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-Cf" => options.push(LersOption::Full),
+            "-Ce" => options.push(LersOption::Ecs),
+            "-t" | "--stdout" => options.push(LersOption::Stdout(true)),
+            "-I" | "--interactive" => options.push(LersOption::Interactive(true)),
+            "-B" | "--batch" => options.push(LersOption::Interactive(false)),
+            "--always-interactive" => options.push(LersOption::AlwaysInteractive(true)),
+            "--never-interactive" => options.push(LersOption::NeverInteractive(true)),
+            "-o" => match args.next() {
+                Some(outfile) => options.push(LersOption::Outfile(outfile)),
+                None => usage(),
+            },
+            _ if arg.starts_with("-o") => options.push(LersOption::Outfile(arg[2..].to_string())),
+            _ if arg.starts_with("--outfile=") => {
+                options.push(LersOption::Outfile(arg["--outfile=".len()..].to_string()))
+            }
+            _ if arg.starts_with("-P") && arg.len() > 2 => {
+                options.push(LersOption::Prefix(arg[2..].to_string()))
+            }
+            _ if arg.starts_with("--prefix=") => {
+                options.push(LersOption::Prefix(arg["--prefix=".len()..].to_string()))
+            }
+            "--header-file" => options.push(LersOption::HeaderFile("lers.yy.h".to_string())),
+            _ if arg.starts_with("--header-file=") => options.push(LersOption::HeaderFile(
+                arg["--header-file=".len()..].to_string(),
+            )),
+            _ if arg.starts_with("--bison-header=") => options.push(LersOption::BisonHeader(
+                arg["--bison-header=".len()..].to_string(),
+            )),
+            _ if filename.is_none() && (arg == "-" || !arg.starts_with('-')) => {
+                filename = Some(arg)
+            }
+            _ => usage(),
+        }
     }
+
+    match filename {
+        Some(filename) => read_and_parse_and_write(&filename, options),
+        None => usage(),
+    }
+}
+
+// the usage goes to the standard error, so that it never ends up in the output
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
 }
 
-fn read_and_parse_and_write(filename: &str) {
-    let source = fs::read_to_string(filename).expect("Unable to read file");
+fn read_and_parse_and_write(filename: &str, options: Vec<LersOption>) {
+    let source = if filename == "-" {
+        io::read_to_string(io::stdin()).expect("Unable to read standard input")
+    } else {
+        fs::read_to_string(filename).expect("Unable to read file")
+    };
     let lexer = Lexer::new(&source);
     let mut parser = Parser::new(lexer);
     let ast = parser.parse();
     let mut codegen = CodeGen::new(ast);
+    for option in options {
+        codegen.add_option(option);
+    }
     let target_code = codegen.generate();
-    fs::write("lers.yy.c", target_code).expect("Unable to write file");
+    match codegen.outfile() {
+        "-" => io::stdout()
+            .write_all(target_code.as_bytes())
+            .expect("Unable to write standard output"),
+        outfile => fs::write(outfile, target_code).expect("Unable to write file"),
+    }
+    if let Some(header_file) = codegen.header_file() {
+        fs::write(header_file, codegen.generate_header()).expect("Unable to write file");
+    }
 }
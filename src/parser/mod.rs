@@ -1,6 +1,6 @@
 use crate::ast::{
     CodeNode, DefinitionNode, DefinitionPairNode, OptionNode, Root, RuleNode, RulePairNode,
-    UserCodeNode,
+    StartConditionNode, UserCodeNode,
 };
 use crate::lexer::{DefinitionToken, Lexer, RuleToken, Token, UsercodeToken};
 use std::collections::HashMap;
@@ -53,7 +53,27 @@ impl<'a> Parser<'a> {
         while let Some(Token::Definition(definition)) = &self.current_token {
             match definition {
                 DefinitionToken::OptionStart => {
-                    definition_node.options = Some(self.parse_options());
+                    let options = self.parse_options();
+                    definition_node
+                        .options
+                        .get_or_insert_default()
+                        .extend(options);
+                }
+                DefinitionToken::InclusiveStart | DefinitionToken::ExclusiveStart => {
+                    let exclusive = *definition == DefinitionToken::ExclusiveStart;
+                    let start_conditions = self.parse_start_conditions(exclusive);
+                    definition_node
+                        .start_conditions
+                        .get_or_insert_default()
+                        .extend(start_conditions);
+                }
+                DefinitionToken::Array => {
+                    definition_node.yytext_type = Some("array");
+                    self.advance();
+                }
+                DefinitionToken::Pointer => {
+                    definition_node.yytext_type = Some("pointer");
+                    self.advance();
                 }
                 DefinitionToken::Name(_) => {
                     definition_node.definitions = Some(self.parse_definition_pairs());
@@ -74,22 +94,80 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_options(&mut self) -> Vec<OptionNode<'a>> {
-        let mut options: Vec<OptionNode> = Vec::new();
+        let mut words = Vec::new();
         self.advance(); // skip the %option token
-        while let Some(Token::Definition(DefinitionToken::Identifier(option))) = self.current_token
+        // options with dashes, digits or values like bison-bridge, 8bit or
+        // prefix="calc" are not identifiers to the lexer
+        while let Some(Token::Definition(
+            DefinitionToken::Identifier(word) | DefinitionToken::Pattern(word),
+        )) = self.current_token
         {
-            options.push(OptionNode::from(option));
+            words.push(word);
             self.advance();
         }
+
+        // name=value, with spaces allowed around the =
+        let mut options = Vec::new();
+        let mut words = words.into_iter().peekable();
+        while let Some(word) = words.next() {
+            let mut option = match word.split_once('=') {
+                Some((name, value)) => OptionNode {
+                    name,
+                    value: Some(value),
+                },
+                None => OptionNode::from(word),
+            };
+            if option.value.is_none()
+                && let Some(next) = words.next_if(|next| next.starts_with('='))
+            {
+                option.value = Some(&next[1..]);
+            }
+            if option.value == Some("") {
+                option.value = Some(
+                    words
+                        .next()
+                        .unwrap_or_else(|| panic!("Expected a value for option {}", option.name)),
+                );
+            }
+            option.value = option.value.map(|value| {
+                value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value)
+            });
+            options.push(option);
+        }
         options
     }
 
+    fn parse_start_conditions(&mut self, exclusive: bool) -> Vec<StartConditionNode<'a>> {
+        let mut start_conditions = Vec::new();
+        self.advance(); // skip the %s or %x token
+        // names with digits or underscores are not identifiers to the lexer
+        while let Some(Token::Definition(
+            DefinitionToken::Identifier(name) | DefinitionToken::Pattern(name),
+        )) = self.current_token
+        {
+            assert!(
+                name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                "Invalid start condition name \"{}\"",
+                name
+            );
+            start_conditions.push(StartConditionNode { name, exclusive });
+            self.advance();
+        }
+        start_conditions
+    }
+
     fn parse_definition_pairs(&mut self) -> Vec<DefinitionPairNode<'a>> {
         let mut definitions: Vec<DefinitionPairNode<'a>> = Vec::new();
         while let Some(Token::Definition(DefinitionToken::Name(name))) = self.current_token {
             self.advance();
-            if let Some(Token::Definition(DefinitionToken::Pattern(definition))) =
-                self.current_token
+            // a definition of letters only is taken as an identifier
+            if let Some(Token::Definition(
+                DefinitionToken::Pattern(definition) | DefinitionToken::Identifier(definition),
+            )) = self.current_token
             {
                 definitions.push(DefinitionPairNode { name, definition });
                 self.advance();
@@ -103,23 +181,68 @@ impl<'a> Parser<'a> {
 
     fn parse_rules(&mut self) -> RuleNode<'a> {
         let mut rules: Vec<RulePairNode<'a>> = Vec::new();
+        // start conditions of the enclosing `<SC>{ }` scopes
+        let mut scopes: Vec<&'a str> = Vec::new();
+        // rules followed by `|`, which take the action of the next rule
+        let mut shared: Vec<(&'a str, Vec<&'a str>)> = Vec::new();
         loop {
-            if let Some(Token::Rule(RuleToken::Newline)) = self.current_token {
-                self.advance();
-                continue;
+            let (pattern, conditions) = match self.current_token {
+                Some(Token::Rule(RuleToken::Newline)) => {
+                    self.advance();
+                    continue;
+                }
+                Some(Token::Rule(RuleToken::ScopeStart(conditions))) => {
+                    scopes.push(conditions);
+                    self.advance();
+                    continue;
+                }
+                Some(Token::Rule(RuleToken::ScopeEnd)) => {
+                    assert!(scopes.pop().is_some(), "Unmatched '}}' in rules");
+                    self.advance();
+                    continue;
+                }
+                Some(Token::Rule(RuleToken::Pattern(pattern))) => (pattern, None),
+                Some(Token::Rule(RuleToken::IndentedPattern(pattern))) if !scopes.is_empty() => {
+                    (pattern, None)
+                }
+                // lex copies indented lines into yylex, lers has no place for them
+                Some(Token::Rule(RuleToken::IndentedPattern(code))) => {
+                    panic!("Indented code in rules is not supported: {}", code)
+                }
+                Some(Token::Rule(RuleToken::ConditionPattern((conditions, pattern)))) => {
+                    (pattern, Some(conditions))
+                }
+                _ => break,
+            };
+            self.advance();
+            // nested scopes add up
+            let mut start_conditions = Vec::new();
+            for condition in scopes.iter().chain(&conditions).flat_map(|c| c.split(',')) {
+                if !start_conditions.contains(&condition) {
+                    start_conditions.push(condition);
+                }
             }
-            if let Some(Token::Rule(RuleToken::Pattern(pattern))) = self.current_token {
-                self.advance();
-                if let Some(Token::Rule(RuleToken::Action(action))) = self.current_token {
+            let action = match self.current_token {
+                Some(Token::Rule(RuleToken::Action(action))) => action,
+                Some(Token::Rule(RuleToken::NextAction)) => {
+                    shared.push((pattern, start_conditions));
                     self.advance();
-                    rules.push(RulePairNode { pattern, action });
                     continue;
-                } else {
-                    panic!("Expected action after pattern");
                 }
+                _ => panic!("Expected action after pattern"),
+            };
+            self.advance();
+            shared.push((pattern, start_conditions));
+            for (pattern, start_conditions) in shared.drain(..) {
+                rules.push(RulePairNode {
+                    pattern,
+                    action,
+                    start_conditions,
+                });
             }
-            break;
         }
+        assert!(shared.is_empty(), "Expected a rule after '|'");
+        assert!(scopes.is_empty(), "Unclosed start condition scope in rules");
         RuleNode { rules: Some(rules) }
     }
 
@@ -154,6 +277,23 @@ impl<'a> Parser<'a> {
             return;
         };
 
+        // the end of the bracket expression starting at start, a leading `]` is
+        // a member and `\]` does not close it
+        fn class_end(pattern: &str, start: usize) -> usize {
+            let bytes = pattern.as_bytes();
+            let mut i = start + 1;
+            if bytes.get(i) == Some(&b'^') {
+                i += 1;
+            }
+            if bytes.get(i) == Some(&b']') {
+                i += 1;
+            }
+            while i < bytes.len() && bytes[i] != b']' {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            (i + 1).min(bytes.len())
+        }
+
         // Helper to recursively expand a pattern
         fn expand_pattern<'a>(pattern: &'a str, defs: &HashMap<&'a str, &'a str>) -> String {
             let mut result = String::from(pattern);
@@ -161,21 +301,38 @@ impl<'a> Parser<'a> {
             while changed {
                 changed = false;
                 let mut new_result = String::new();
+                let mut quoted = false;
                 let mut i = 0;
                 while i < result.len() {
-                    if result[i..].starts_with('{') {
-                        if let Some(end) = result[i..].find('}') {
-                            let name = &result[i + 1..i + end];
-                            if let Some(def) = defs.get(name) {
-                                new_result.push_str(def);
-                                i += end + 1;
-                                changed = true;
-                                continue;
-                            }
-                        }
+                    if !quoted
+                        && result[i..].starts_with('{')
+                        && let Some(end) = result[i..].find('}')
+                        && let Some(def) = defs.get(&result[i + 1..i + end])
+                    {
+                        // keep `{name}{3}` and `{name}*` applying to the whole definition
+                        new_result.push('(');
+                        new_result.push_str(def);
+                        new_result.push(')');
+                        i += end + 1;
+                        changed = true;
+                        continue;
+                    }
+                    // `"{name}"`, `[{name}]` and `\{name}` are not references, and a
+                    // quote in a class does not start a string
+                    if !quoted && result[i..].starts_with('[') {
+                        let end = class_end(&result, i);
+                        new_result.push_str(&result[i..end]);
+                        i = end;
+                        continue;
                     }
-                    new_result.push(result.as_bytes()[i] as char);
-                    i += 1;
+                    let mut len = result[i..].chars().next().unwrap().len_utf8();
+                    match &result[i..i + len] {
+                        "\"" => quoted = !quoted,
+                        "\\" => len += result[i + 1..].chars().next().map_or(0, char::len_utf8),
+                        _ => {}
+                    }
+                    new_result.push_str(&result[i..i + len]);
+                    i += len;
                 }
                 result = new_result;
             }
@@ -183,12 +340,12 @@ impl<'a> Parser<'a> {
         }
 
         // apply to rules
-        if let Some(rule_node) = &mut root.rule_node {
-            if let Some(rules) = &mut rule_node.rules {
-                for rule in rules.iter_mut() {
-                    let expanded = expand_pattern(rule.pattern, &definitions);
-                    rule.pattern = Box::leak(expanded.into_boxed_str());
-                }
+        if let Some(rule_node) = &mut root.rule_node
+            && let Some(rules) = &mut rule_node.rules
+        {
+            for rule in rules.iter_mut() {
+                let expanded = expand_pattern(rule.pattern, &definitions);
+                rule.pattern = Box::leak(expanded.into_boxed_str());
             }
         }
     }
@@ -216,16 +373,18 @@ name3       pattern3
 {digit}+    { action1(); }
 {number}    { action2(); }
 pattern3    { action3(); }
+"{digit}"\{digit}  { action4(); }
+["]{digit}[{digit}]  { action5(); }
 
 %%
 
 /* user code */
 void helper() {}"#;
-        let lexer = Lexer::new(&source);
+        let lexer = Lexer::new(source);
         let mut parser = Parser::new(lexer);
         let target_ast = Root {
             definition_node: Some(DefinitionNode {
-                options: Some(vec![OptionNode { value: "noyywrap" }]),
+                options: Some(vec![OptionNode::from("noyywrap")]),
                 code: Some(CodeNode {
                     value: "    c code block",
                 }),
@@ -243,20 +402,35 @@ void helper() {}"#;
                         definition: "pattern3",
                     },
                 ]),
+                start_conditions: None,
+                yytext_type: None,
             }),
             rule_node: Some(RuleNode {
                 rules: Some(vec![
                     RulePairNode {
-                        pattern: "[0-9]+",
+                        pattern: "([0-9])+",
                         action: "{ action1(); }",
+                        start_conditions: Vec::new(),
                     },
                     RulePairNode {
-                        pattern: "[0-9]+",
+                        pattern: "(([0-9])+)",
                         action: "{ action2(); }",
+                        start_conditions: Vec::new(),
                     },
                     RulePairNode {
                         pattern: "pattern3",
                         action: "{ action3(); }",
+                        start_conditions: Vec::new(),
+                    },
+                    RulePairNode {
+                        pattern: r#""{digit}"\{digit}"#,
+                        action: "{ action4(); }",
+                        start_conditions: Vec::new(),
+                    },
+                    RulePairNode {
+                        pattern: r#"["]([0-9])[{digit}]"#,
+                        action: "{ action5(); }",
+                        start_conditions: Vec::new(),
                     },
                 ]),
             }),
@@ -266,4 +440,123 @@ void helper() {}"#;
         };
         assert_eq!(parser.parse(), target_ast);
     }
+
+    #[test]
+    fn start_conditions() {
+        let source = r#"
+%s STRING
+%x COMMENT IN_TAG
+%array
+%%
+"/*"            { BEGIN(COMMENT); }
+<COMMENT>{
+    "*/"        { BEGIN(INITIAL); }
+    <IN_TAG>.   { tag(); }
+}
+<*>\n           { newline(); }
+%%"#;
+        let root = Parser::new(Lexer::new(source)).parse();
+        let definition_node = root.definition_node.unwrap();
+        assert_eq!(definition_node.yytext_type, Some("array"));
+        assert_eq!(
+            definition_node.start_conditions,
+            Some(vec![
+                StartConditionNode {
+                    name: "STRING",
+                    exclusive: false,
+                },
+                StartConditionNode {
+                    name: "COMMENT",
+                    exclusive: true,
+                },
+                StartConditionNode {
+                    name: "IN_TAG",
+                    exclusive: true,
+                },
+            ])
+        );
+        let conditions = root
+            .rule_node
+            .unwrap()
+            .rules
+            .unwrap()
+            .into_iter()
+            .map(|rule| (rule.pattern, rule.start_conditions))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            conditions,
+            vec![
+                ("\"/*\"", vec![]),
+                ("\"*/\"", vec!["COMMENT"]),
+                (".", vec!["COMMENT", "IN_TAG"]),
+                ("\\n", vec!["*"]),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Indented code in rules is not supported: int")]
+    fn indented_code() {
+        let source = "%%\n    int depth = 0;\na ECHO;\n%%";
+        Parser::new(Lexer::new(source)).parse();
+    }
+
+    #[test]
+    fn options() {
+        let source = r#"%option noyywrap 8bit prefix="calc"
+%option outfile = "scan.c" yy_scan_string extra-type="struct state *"
+%%
+%%"#;
+        let root = Parser::new(Lexer::new(source)).parse();
+        let options = root.definition_node.unwrap().options.unwrap();
+        assert_eq!(
+            options,
+            vec![
+                OptionNode::from("noyywrap"),
+                OptionNode::from("8bit"),
+                OptionNode {
+                    name: "prefix",
+                    value: Some("calc"),
+                },
+                OptionNode {
+                    name: "outfile",
+                    value: Some("scan.c"),
+                },
+                OptionNode::from("yy_scan_string"),
+                OptionNode {
+                    name: "extra-type",
+                    value: Some("struct state *"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_actions() {
+        let source = r#"
+%%
+"if"            |
+<COMMENT>"else" |
+"while"         { keyword(); }
+[a-z]+          { name(); }
+%%"#;
+        let rules = Parser::new(Lexer::new(source))
+            .parse()
+            .rule_node
+            .unwrap()
+            .rules
+            .unwrap()
+            .into_iter()
+            .map(|rule| (rule.pattern, rule.action, rule.start_conditions))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            vec![
+                ("\"if\"", "{ keyword(); }", vec![]),
+                ("\"else\"", "{ keyword(); }", vec!["COMMENT"]),
+                ("\"while\"", "{ keyword(); }", vec![]),
+                ("[a-z]+", "{ name(); }", vec![]),
+            ]
+        );
+    }
 }